@@ -1,48 +1,87 @@
-#![feature(slicing_syntax)]
-#![feature(box_syntax)]
-
-extern crate syntax;
-
-use syntax::parse::new_parse_sess;
+use syntax::parse::new_parse_sess_special_handler;
 use syntax::parse::parser::Parser;
-use syntax::diagnostic::ColorConfig;
-use syntax::parse::lexer::StringReader;
+use syntax::diagnostic;
+use syntax::parse::lexer::{StringReader, Reader, TokenAndSpan};
 use syntax::codemap::CodeMap;
-use syntax::ext::quote::rt::ToSource;
-use syntax::codemap::Spanned;
+use syntax::codemap::{Span, BytePos, Pos};
+use syntax::codemap;
+use syntax::parse::token;
+use syntax::parse::token::{keywords, special_idents};
 use syntax::ast::*;
 use syntax::ast;
+use syntax::ptr;
 
 use std::fmt;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::thread::Builder;
+use std::io::util::NullWriter;
 
-pub type Box<T> = syntax::ptr::P<T>;
+pub type Box<T> = ptr::P<T>;
 
 trait ToHtml<Style> where Style : ToCss {
     fn to_html(&self, f : &mut fmt::Formatter, original : &str, style : Style) -> fmt::Result {
-        let mut span_buff = Vec::new();
-        self.add_span_tokens(&mut span_buff);
+        let mut marks = Vec::new();
+        self.add_span_tokens(&mut marks);
+
+        //the codemap drops a leading byte order mark, so spans start after it
+        let (bom, original) = split_bom(original);
+
+        let span_buff = classify(original, marks);
 
-        restore_whitespace(original, &mut span_buff);
+        let span_buff = match self.error() {
+            Some((span, message)) => mark_error(span_buff, span, message),
+            None                  => span_buff
+        };
 
-        write!(f, "<pre style=\"background:#efffef\"><code>");
+        write!(f, "<pre style=\"background:#efffef\"><code>{}", bom);
 
+        //only whitespace is left between the spans
+        let mut pos = 0;
         for token in span_buff.iter() {
-            write!(f, "<span {}>{}</span>", style.clone().to_css(&token.class), token.text);
+            let lo = token.span.lo.to_usize();
+            let hi = token.span.hi.to_usize();
+
+            write!(f, "{}", escape(&original[pos..lo]));
+            write!(f, "<span {}=\"{}\"", style.attribute(), escape(&style.clone().to_css(&token.class)[]));
+
+            if let Some(ref title) = token.title {
+                write!(f, " title=\"{}\"", escape(&title[]));
+            }
+
+            write!(f, ">{}</span>", escape(&original[lo..hi]));
+
+            pos = hi;
         }     
 
+        write!(f, "{}", escape(&original[pos..]));
+
         write!(f, "</code></pre>");
 
         Ok(())
     }
 
+    //Marks the spans of source this node knows the class of. Everything
+    //left unmarked keeps the class the lexer gives it.
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>);
+
+    //The span of source the parser rejected, and why
+    fn error(&self) -> Option<(Span, &str)> {
+        None
+    }
 }
 
 pub trait ToCss : Clone {
+    //The attribute the css goes in, "class" unless the css is inline
+    fn attribute(&self) -> &'static str {
+        "class"
+    }
+
+    //The unescaped value of that attribute for the given class
     fn to_css(self, clazz : &Class) -> String;
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Copy, PartialEq)]
 pub enum Class {
     //If nothing else
     Default,
@@ -52,6 +91,7 @@ pub enum Class {
     Type,
     Keyword,
     Comment,
+    DocComment,
     Operator,
     Attribute,
 
@@ -78,20 +118,29 @@ pub enum Class {
     LifeTime,
     FnCall,
     ReturnStmt, //for implicit and explicit returns...darker orange?
-    MacCall
+    MacCall,
+
+    //Source the parser rejected
+    Error
 }
 
 #[derive(Show)]
 struct SpanToken {
     class : Class,
-    text  : String
+    span  : Span,
+    //which of the source tokens in the span take the class
+    reach : Reach,
+    //shown when hovering over the token
+    title : Option<String>
 }
 
 impl SpanToken {
-    fn new(class : Class, text : String) -> SpanToken {
+    fn new(class : Class, span : Span, reach : Reach) -> SpanToken {
         SpanToken {
             class : class,
-            text  : text
+            span  : span,
+            reach : reach,
+            title : None
         }
     }
 }
@@ -102,6 +151,20 @@ impl fmt::String for SpanToken {
     }
 }
 
+#[derive(Show, Clone, Copy, PartialEq)]
+enum Reach {
+    //every token, e.g. a literal or a binding's ident
+    All,
+    //the first identifier with this name, for the names of items, whose
+    //idents the AST keeps no span of
+    Named(ast::Name),
+    //the last identifier outside of any brackets, i.e. the final segment of
+    //a path or the name an import is bound to
+    Last,
+    //whatever is still Default once every other mark is in
+    Plain
+}
+
 struct Highlighted<'a, Markup : ToHtml<Style>, Style : ToCss>(&'a str, Markup, Style);
 
 impl<'a, Markup, Style> fmt::String for Highlighted<'a, Markup, Style> 
@@ -119,6 +182,34 @@ where Markup : ToHtml<Style>, Style : ToCss {
 //so then you could change the tohtml characteristics 
 struct Declaration<T>(T); 
 
+//A fn declaration whose first argument may be written as `self`, `&self`, ...
+struct MethodDecl<'a>(&'a ast::ExplicitSelf_, &'a Box<ast::FnDecl>);
+
+//A path whose final segment is tagged with the given class instead of Type
+struct TaggedPath<'a>(&'a ast::Path, Class);
+
+//The trailing expression of a block, or the one a `return` gives back
+struct Returned<'a>(&'a Box<ast::Expr>);
+
+//A pattern whose new bindings are tagged with the given class
+struct Binding<'a>(&'a Box<ast::Pat>, Class);
+
+//The `for<'a, 'b>` of a higher-ranked fn type or trait bound
+struct HigherRanked<'a>(&'a Vec<ast::LifetimeDef>);
+
+//The `: 'b + 'c` bounds of a lifetime
+struct LifetimeBounds<'a>(&'a Vec<ast::Lifetime>);
+
+//The `: Trait + 'a = Default` following a type parameter
+struct TyParamRest<'a>(&'a ast::TyParam);
+
+//Bounds whose traits are tagged as constraints on a generic
+struct Constraint<'a>(&'a ast::TyParamBounds);
+
+//A run of token trees, where `$x` is tagged as a macro param, and so is the
+//`expr` of `$x:expr` when they are a macro_rules! matcher
+struct TokenTrees<'a>(&'a [ast::TokenTree], bool);
+
 ///////////////////////////////////////////////////////////////////////////////
 //                                                                           //
 //                               Convenience Macros                          //                                      
@@ -126,17 +217,14 @@ struct Declaration<T>(T);
 ///////////////////////////////////////////////////////////////////////////////
 
 macro_rules! add_token{
-    ($vec:ident, $class:ident, $string:expr) => (
+    ($vec:ident, $class:ident, $span:expr) => (
         {
-            $vec.push(tok!($class, $string));
+            $vec.push(SpanToken::new(Class::$class, $span, Reach::All));
         }
     );
-}
-
-macro_rules! tok{
-    ($class:ident, $string:expr) => (
+    ($vec:ident, $class:ident, $span:expr, $reach:expr) => (
         {
-            SpanToken::new(Class::$class, $string.to_string())
+            $vec.push(SpanToken::new(Class::$class, $span, $reach));
         }
     );
 }
@@ -152,37 +240,6 @@ macro_rules! add_spans{
     );
 }
 
-macro_rules! between_each {
-    ($thing:pat , $things:expr => $outter_stmts:block => $inbetween_stmts:block) 
-        => 
-    (
-        {
-            let len = $things.len();
-            if len != 0 {
-                let mut parts = $things.iter();
-
-                match parts.next() {
-                    Some($thing) => $outter_stmts,
-                    None   => panic!("called between_each! on an empty container!")
-                };
-
-                for $thing in parts.take(len - 2) {
-                    $inbetween_stmts
-                    $outter_stmts
-                }
-
-                match parts.last() {
-                    Some($thing) => {
-                        $inbetween_stmts
-                        $outter_stmts
-                    },
-                    None   => {}
-                };
-            }
-        }
-    );
-}
-
 ///////////////////////////////////////////////////////////////////////////////
 //                                                                           //
 //                               Entry point module                          //                                      
@@ -190,32 +247,143 @@ macro_rules! between_each {
 ///////////////////////////////////////////////////////////////////////////////
 
 pub mod highlight {
-    use super::{ToCss, Highlighted , str_to_crate/*, str_to_expr, str_to_stmt, str_to_item, str_to_view_item*/};
+    use super::{ToCss, Highlighted, Unparsed, Recovered, HighlightError, ErrorKind};
+    use super::{round_trip_mismatch, recovery_points, split_bom, mk_span};
+    use super::{with_parser, with_lexer, parse_crate, parse_expr, parse_stmt, parse_item, parse_ty, parse_pat};
+
+    use syntax::codemap::Pos;
+
+    use std::cmp;
+
+    pub fn program<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "program", parse_crate, move |crayt| format!("{}", Highlighted(src, crayt, style)))
+    }
+
+    //Highlights src and checks that stripping the markup gives src back byte
+    //for byte, failing at the offset where the two first differ
+    pub fn verified_program<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        let html = try!(program(src, style));
+
+        match round_trip_mismatch(src, &html[]) {
+            Some(offset) => {
+                //spans count from after the byte order mark, like the parser's
+                let (bom, _) = split_bom(src);
+                let pos = cmp::max(offset, bom.len()) - bom.len();
+
+                Err(HighlightError {
+                    kind    : ErrorKind::RoundTrip,
+                    message : format!("the highlighted output stops matching the source at byte {}", pos),
+                    span    : Some(mk_span(pos, pos))
+                })
+            },
+            None => Ok(html)
+        }
+    }
+
+    pub fn expr<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "expression", parse_expr, move |expr| format!("{}", Highlighted(src, expr, style)))
+    }
+
+    pub fn stmt<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "statement", parse_stmt, move |stmt| format!("{}", Highlighted(src, stmt, style)))
+    }
+
+    pub fn item<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "item", parse_item, move |item| format!("{}", Highlighted(src, item, style)))
+    }
+
+    pub fn ty<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "type", parse_ty, move |ty| format!("{}", Highlighted(src, ty, style)))
+    }
+
+    pub fn pat<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_parser(src, "pattern", parse_pat, move |pat| format!("{}", Highlighted(src, pat, style)))
+    }
 
-    pub fn program<Style : ToCss>(src : &str, style : Style) -> String {
-        let crayt = str_to_crate(src);
-        format!("{}", Highlighted(src, crayt, style))
+    //Highlights src token by token without parsing it, for snippets that
+    //aren't valid rust. Fails only when src can't be lexed, e.g. when a string
+    //or comment is never closed.
+    pub fn tokens<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        with_lexer(src, move || format!("{}", Highlighted(src, Unparsed, style)))
     }
 
-    // fn expr<Style : ToCss>(src : &str, style : Style) -> String {
-    //     let expr = str_to_expr(src);
-    //     format!("{}", Highlighted(src, expr, style))
-    // }
+    //Highlights src as a program even when it only parses partway: the code
+    //before the error semantically, the rest token by token, and the tokens
+    //the error is about as an Error titled with its message
+    pub fn tolerant<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        let error = match program(src, style.clone()) {
+            Ok(html) => { return Ok(html); },
+            Err(e)   => e
+        };
+
+        let span = match (error.kind, error.span) {
+            (ErrorKind::Syntax, Some(span)) => span,
+            _                               => { return Err(error); }
+        };
+
+        //spans count from after the byte order mark, which the codemap drops
+        let (_, body) = split_bom(src);
+
+        let lo = span.lo.to_usize();
+        let hi = span.hi.to_usize();
+
+        //the span has to be one into body to be recovered from and marked
+        if lo > hi || hi > body.len() || !body.is_char_boundary(lo) || !body.is_char_boundary(hi) {
+            return Err(error);
+        }
+
+        //the cuts come from lexing all of body, in case the error is inside a
+        //token, or else just what precedes it, in case the lexer gave up
+        //further on. The empty prefix is left when neither lexes.
+        let points = recovery_points(body, lo)
+            .or_else(|_| recovery_points(&body[..lo], lo))
+            .unwrap_or(vec![(0, String::new())]);
+
+        //the latest prefix that parses once its delimiters are closed, which
+        //is at worst the empty one
+        for (cut, closers) in points.into_iter() {
+            let style   = style.clone();
+            let message = error.message.clone();
+            let prefix  = format!("{}{}", &body[..cut], closers);
+
+            let recovered = with_parser(&prefix[], "program", parse_crate, move |crayt| {
+                format!("{}", Highlighted(src, Recovered(crayt, cut, span, message), style))
+            });
+
+            if let Ok(html) = recovered {
+                return Ok(html);
+            }
+        }
 
-    // fn stmt<Style : ToCss>(src : &str, style : Style) -> String {
-    //     let stmt = str_to_stmt(src);
-    //     format!("{}", Highlighted(src, stmt, style))
-    // }
+        Err(error)
+    }
 
-    // fn item<Style : ToCss>(src : &str, style : Style) -> String {
-    //     let item = str_to_item(src);
-    //     format!("{}", Highlighted(src, item, style))
-    // }
+    //Highlights src with the first grammar that parses all of it, trying
+    //whole programs first and patterns last. When none do, a program broken
+    //partway is marked where it breaks, and anything else has its tokens
+    //highlighted.
+    pub fn auto<Style : ToCss + Send>(src : &str, style : Style) -> Result<String, HighlightError> {
+        let grammars : [fn(&str, Style) -> Result<String, HighlightError>; 5] = [program, expr, stmt, ty, pat];
+
+        let mut partway = false;
+
+        for (i, grammar) in grammars.iter().enumerate() {
+            match (*grammar)(src, style.clone()) {
+                Ok(html) => { return Ok(html); },
+                Err(HighlightError { kind : ErrorKind::Syntax, span : Some(span), .. }) if i == 0 => {
+                    partway = span.lo.to_usize() > 0;
+                },
+                Err(error @ HighlightError { kind : ErrorKind::Internal, .. }) => { return Err(error); },
+                Err(_) => {}
+            };
+        }
 
-    // fn view_item<Style : ToCss>(src : &str, style : Style) -> String {
-    //     let item = str_to_view_item(src);
-    //     format!("{}", Highlighted(src, item, style))
-    // }
+        if partway {
+            tolerant(src, style.clone()).or_else(|_| tokens(src, style))
+        } else {
+            tokens(src, style)
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -225,32 +393,42 @@ pub mod highlight {
 ///////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone)]
-struct StyleSheet;
+pub struct StyleSheet;
 impl ToCss for StyleSheet {
     fn to_css(self, clazz : &Class) -> String {
         match *clazz {
-            Class::Type        => "class=\".type\"",
-            Class::PreludeType => "class=\".prelude-type\"", 
-            _                  => "class=\".default\""
+            Class::Type        => "type",
+            Class::PreludeType => "prelude-type", 
+            Class::Comment     => "comment",
+            Class::DocComment  => "doc-comment",
+            Class::Error       => "error",
+            _                  => "default"
         }.to_string()
     }
 }
 
 #[derive(Clone)]
-struct DefaultInline;
+pub struct DefaultInline;
 impl ToCss for DefaultInline {
+    fn attribute(&self) -> &'static str {
+        "style"
+    }
+
     fn to_css(self, clazz : &Class) -> String {
         match *clazz {
-            Class::Keyword     => "style=\"color:#ea4646;font-weight:bold\"",//"style=\"color:#07c7dd\"",
-            Class::Operator    => "style=\"color:#07c7dd\"",
-            Class::CrateDecl   => "style=\"color:#f4824e\"",
-            Class::UseDecl | Class::GenDecl | Class::TyDecl    => "style=\"color:#f4824e\"",
-            Class::LifeTimeDecl => "style=\"color:#ff4242\"",
-            Class::Attribute   => "style=\"color:#7f7f7f\"",
-
-            Class::Type        => "style=\"color:#000000\"",
-            Class::PreludeType => "style=\"color:#00ff0b\"",
-            _                     => "style=\"\""
+            Class::Keyword     => "color:#ea4646;font-weight:bold",//"color:#07c7dd",
+            Class::Operator    => "color:#07c7dd",
+            Class::CrateDecl   => "color:#f4824e",
+            Class::UseDecl | Class::GenDecl | Class::TyDecl    => "color:#f4824e",
+            Class::LifeTimeDecl => "color:#ff4242",
+            Class::Attribute   => "color:#7f7f7f",
+            Class::Comment     => "color:#9a9a9a;font-style:italic",
+            Class::DocComment  => "color:#5f8f5f;font-style:italic",
+            Class::Error       => "background:#ffdede;border-bottom:2px dotted #e00000",
+
+            Class::Type        => "color:#000000",
+            Class::PreludeType => "color:#00ff0b",
+            _                     => ""
         }.to_string()
     }
 }
@@ -264,138 +442,53 @@ impl ToCss for DefaultInline {
 impl<Style> ToHtml<Style> for ast::Crate where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
 
+        add_spans!(self.attrs.iter(), tokens);
+
         add_spans!(self.module.view_items.iter(), tokens);
 
         add_spans!(self.module.items.iter(), tokens);
     }
 }
 
+impl<Style> ToHtml<Style> for ast::Mod where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+
+        add_spans!(self.view_items.iter(), tokens);
+
+        add_spans!(self.items.iter(), tokens);
+    }
+}
+
 impl<Style> ToHtml<Style> for ast::ViewItem where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
 
         add_spans!(self.attrs.iter(), tokens);
 
-        let vis = &self.vis as &ToHtml<Style>;
-        vis.add_span_tokens(tokens);
-
         match self.node {
+            //extern crate foo; and extern crate "foo-bar" as foo;
             ViewItemExternCrate(ref name, _, _) => {
-                add_token!(tokens, Keyword, "extern");
-                add_token!(tokens, Keyword, "crate");
-                add_token!(tokens, CrateDecl, name.as_str());
-                add_token!(tokens, Operator, ";");
+                add_token!(tokens, CrateDecl, after_attrs(self.span, &self.attrs[]), Reach::Named(name.name));
             },
-            ViewItemUse(ref path) => {
-
-                add_token!(tokens, Keyword, "use");
-
-                match path.node {
+            ViewItemUse(ref view_path) => {
+                match view_path.node {
                     //use a::b::c; and use a::b::c as d;
-                    ViewPathSimple(ref name, ref path, _) => {
-                        let mut segments = path.segments.iter();
-
-                        match segments.next() {
-                            Some(segment) => {
-                                if path.global {
-                                    add_token!(tokens, Operator, "::");
-                                }
-
-                                add_token!(tokens, Default, segment.identifier.as_str());
-                            },
-                            None => { panic!("this should never happen") }
-                        };
-
-                        for segment in segments.take(path.segments.len() - 2) {
-                            add_token!(tokens, Operator, "::");
-                            add_token!(tokens, Default, segment.identifier.as_str());
-                        }
-
-                        add_token!(tokens, Operator, "::");
-
-                        match segments.last() {
-                            Some(segment) => {
-                                if false/* TODO: it has an 'as' stmt */ {
-                                    add_token!(tokens, Default, segment.identifier.as_str());
-                                    add_token!(tokens, Keyword, "as");
-                                    add_token!(tokens, UseDecl, name.as_str());
-                                } else {
-
-                                    add_token!(tokens, UseDecl, segment.identifier.as_str());
-                                }
-                            },
-                            None => { panic!("this should never happen") }
-                        };
-                    },
-                    ViewPathGlob(ref path, _) => {
-                        let mut segments = path.segments.iter();
-
-                        match segments.next() {
-                            Some(segment) => {
-                                if path.global {
-                                    add_token!(tokens, Operator, "::");
-                                }
-
-                                add_token!(tokens, Default, segment.identifier.as_str());
-                            },
-                            None => { panic!("this should never happen") }
-                        };
-
-                        for segment in segments.take(path.segments.len() - 1) {
-                            add_token!(tokens, Operator, "::");
-                            add_token!(tokens, Default, segment.identifier.as_str());
-                        }
-
-                        add_token!(tokens, Operator, "::");
-                        add_token!(tokens, UseDecl, "*");
+                    ViewPathSimple(..) => {
+                        add_token!(tokens, UseDecl, view_path.span, Reach::Last);
                     },
-                    ViewPathList(ref path, ref imports, _) => {
-                        let mut segments = path.segments.iter();
-
-                        match segments.next() {
-                            Some(segment) => {
-                                if path.global {
-                                    add_token!(tokens, Operator, "::");
-                                }
-
-                                add_token!(tokens, Default, segment.identifier.as_str());
-                            },
-                            None => { panic!("this should never happen") }
-                        };
-
-                        for segment in segments.take(path.segments.len() - 2) {
-                            add_token!(tokens, Operator, "::");
-                            add_token!(tokens, Default, segment.identifier.as_str());
-                        }
-
-                        add_token!(tokens, Operator, "::");
-                        add_token!(tokens, Operator, "{");
-
-                        let len = imports.len() - 1;
-                        for import in imports.iter().take(len) {
+                    //use a::b::*;
+                    ViewPathGlob(..) => {},
+                    //use a::b::{self, c, d};
+                    ViewPathList(_, ref imports, _) => {
+                        for import in imports.iter() {
                             match import.node {
-                                PathListIdent{ name , .. } => {
-                                    add_token!(tokens, UseDecl, name.as_str());
-                                    add_token!(tokens, Operator, ",");
+                                PathListIdent{ .. } => {
+                                    add_token!(tokens, UseDecl, import.span);
                                 },
-                                _ => panic!("this should never happen")
+                                PathListMod{ .. } => {}
                             };
                         }
-
-                        let spanned_import = imports[len];
-
-                        match spanned_import.node {
-                            PathListIdent{ name, .. } => {
-                                add_token!(tokens, UseDecl, name.as_str());
-                            },
-                            _ => { panic!("this should never happen") }
-                        };
-
-                        
-                        add_token!(tokens, Operator, "}");
                     }
                 };
-
-                add_token!(tokens, Operator, ";");
             }
         };
     }
@@ -403,17 +496,42 @@ impl<Style> ToHtml<Style> for ast::ViewItem where Style : ToCss {
 
 impl<Style> ToHtml<Style> for ast::Attribute where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
-        tokens.push(tok!(Attribute, self.node.to_source()));
+        //`/// ...` is a doc comment to the lexer already
+        if self.node.is_sugared_doc {
+            return;
+        }
+
+        let meta = &self.node.value;
+
+        //the `#[` or `#![` before the meta item, and the `]` after it
+        add_token!(tokens, Attribute, codemap::mk_sp(self.span.lo, meta.span.lo));
+        add_token!(tokens, Attribute, codemap::mk_sp(meta.span.hi, self.span.hi));
+
+        let meta = meta as &ToHtml<Style>;
+        meta.add_span_tokens(tokens);
     }
 }
 
-impl<Style> ToHtml<Style> for ast::Visibility where Style : ToCss {
+impl<Style> ToHtml<Style> for Box<ast::MetaItem> where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
-        match *self {
-            ast::Visibility::Public => {
-                tokens.push(tok!(Keyword, "pub"));
+        match self.node {
+            //#[test]
+            MetaWord(_) => {
+                add_token!(tokens, Attribute, self.span);
+            },
+            //#[derive(Clone, Show)]
+            MetaList(ref name, ref items) => {
+                add_token!(tokens, Attribute, self.span, Reach::Named(token::intern(name.get())));
+
+                add_spans!(items.iter(), tokens);
             },
-            ast::Visibility::Inherited => {}
+            //#[doc = "..."]
+            MetaNameValue(ref name, ref lit) => {
+                add_token!(tokens, Attribute, self.span, Reach::Named(token::intern(name.get())));
+
+                let lit = lit as &ToHtml<Style>;
+                lit.add_span_tokens(tokens);
+            }
         };
     }
 }
@@ -422,346 +540,1865 @@ impl<Style> ToHtml<Style> for Box<ast::Item> where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
         add_spans!(self.attrs.iter(), tokens);
 
-        let vis = &self.vis as &ToHtml<Style>;
-        vis.add_span_tokens(tokens);
+        let named = after_attrs(self.span, &self.attrs[]);
 
         match self.node {
-            ItemStatic(ref ty, ref mutability, ref expr) => {
+            ItemStatic(ref ty, _, ref expr) | ItemConst(ref ty, ref expr) => {
+                add_token!(tokens, VarDecl, named, Reach::Named(self.ident.name));
 
-            },
-            ItemConst(ref ty, ref expr) => {
+                let typ = ty as &ToHtml<Style>;
+                typ.add_span_tokens(tokens);
 
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
             },
-            ItemFn(ref fn_decl, ref unsafety, ref abi, ref generics, ref block) => {
+            ItemFn(ref fn_decl, _, _, ref generics, ref block) => {
+                add_token!(tokens, FnDecl, named, Reach::Named(self.ident.name));
+
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
 
+                let decl = fn_decl as &ToHtml<Style>;
+                decl.add_span_tokens(tokens);
+
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
+
+                let body = block as &ToHtml<Style>;
+                body.add_span_tokens(tokens);
             },
             ItemMod(ref module) => {
-
+                let module = module as &ToHtml<Style>;
+                module.add_span_tokens(tokens);
             },
             ItemForeignMod(ref foreign_mod) => {
+                add_spans!(foreign_mod.view_items.iter(), tokens);
 
+                add_spans!(foreign_mod.items.iter(), tokens);
             },
             ItemTy(ref ty, ref generics) => {
-                add_token!(tokens, Keyword, "type");
-                add_token!(tokens, TyDecl, self.ident.name.as_str());
+                add_token!(tokens, TyDecl, named, Reach::Named(self.ident.name));
 
                 let gen = &Declaration(generics) as &ToHtml<Style>;
                 gen.add_span_tokens(tokens);
 
-                add_token!(tokens, Operator, "=");
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
 
                 let typ = ty as &ToHtml<Style>;
                 typ.add_span_tokens(tokens);
-
-                add_token!(tokens, Operator, ";");
             },
             ItemEnum(ref enum_def, ref generics) => {
+                add_token!(tokens, TyDecl, named, Reach::Named(self.ident.name));
 
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
+
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
+
+                add_spans!(enum_def.variants.iter(), tokens);
             },
             ItemStruct(ref struct_def, ref generics) => {
+                add_token!(tokens, TyDecl, named, Reach::Named(self.ident.name));
+
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
+
+                add_spans!(struct_def.fields.iter(), tokens);
 
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
             },
-            ItemTrait(ref unsafety, ref generics, ref ty_param_bounds, ref trait_items) => {
+            ItemTrait(_, ref generics, ref ty_param_bounds, ref trait_items) => {
+                add_token!(tokens, TyDecl, named, Reach::Named(self.ident.name));
 
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
+
+                let bounds = &Constraint(ty_param_bounds) as &ToHtml<Style>;
+                bounds.add_span_tokens(tokens);
+
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
+
+                add_spans!(trait_items.iter(), tokens);
             },
-            ItemImpl(ref unsafety, ref polarity, ref generics, ref trait_ref, ref ty, ref  impls) => {
+            ItemImpl(_, _, ref generics, ref trait_ref, ref ty, ref impls) => {
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
+
+                match *trait_ref {
+                    Some(ref trait_ref) => {
+                        let trait_ref = trait_ref as &ToHtml<Style>;
+                        trait_ref.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+
+                let typ = ty as &ToHtml<Style>;
+                typ.add_span_tokens(tokens);
+
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
 
+                add_spans!(impls.iter(), tokens);
             },
             ItemMac(ref mac) => {
+                match mac.node {
+                    //macro_rules! name { ... }
+                    MacInvocTT(ref path, ref tts, _) if self.ident.name != special_idents::invalid.name => {
+                        //the name is looked for after the path, which it may be spelled like
+                        add_token!(tokens, MacDecl, codemap::mk_sp(path.span.hi, self.span.hi), Reach::Named(self.ident.name));
+
+                        let path = &TaggedPath(path, Class::MacCall) as &ToHtml<Style>;
+                        path.add_span_tokens(tokens);
+
+                        //each rule's matcher is the tree before its `=>`
+                        for i in 0..tts.len() {
+                            let matcher = match tts.get(i + 1) {
+                                Some(&TtToken(_, token::FatArrow)) => true,
+                                _                                  => false
+                            };
 
+                            let tt = &TokenTrees(&tts[i..i + 1], matcher) as &ToHtml<Style>;
+                            tt.add_span_tokens(tokens);
+                        }
+                    },
+                    MacInvocTT(..) => {
+                        let mac = mac as &ToHtml<Style>;
+                        mac.add_span_tokens(tokens);
+                    }
+                };
             }
         }
     }
 }
 
-impl<Style> ToHtml<Style> for ast::Generics where Style : ToCss {
+impl<Style> ToHtml<Style> for Box<ast::ForeignItem> where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.attrs.iter(), tokens);
 
-        if self.lifetimes.len() == 0 && self.ty_params.len() == 0 && self.where_clause.predicates.len() == 0 {
-            return
-        }
-
-
-        add_token!(tokens, Operator, "<");
-
-        between_each!(lifetime , self.lifetimes => {
-            add_token!(tokens, LifeTime, lifetime.lifetime.name.as_str());
-        } => {
-            add_token!(tokens, Operator, ",");            
-        });
+        let named = after_attrs(self.span, &self.attrs[]);
 
-        if self.ty_params.len() > 0 && self.lifetimes.len() > 0 {
-            add_token!(tokens, Operator, ",");            
-        }
+        match self.node {
+            ForeignItemFn(ref fn_decl, ref generics) => {
+                add_token!(tokens, FnDecl, named, Reach::Named(self.ident.name));
 
-        between_each!(ty_param , self.ty_params.as_slice() => {
-            add_token!(tokens, Gen, ty_param.ident.name.as_str());
-        } => {
-            add_token!(tokens, Operator, ",");            
-        });
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
 
-        add_token!(tokens, Operator, ">");
+                let decl = fn_decl as &ToHtml<Style>;
+                decl.add_span_tokens(tokens);
 
-        if self.where_clause.predicates.len() > 0 {
-            add_token!(tokens, Keyword, "where");
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
+            },
+            ForeignItemStatic(ref ty, _) => {
+                add_token!(tokens, VarDecl, named, Reach::Named(self.ident.name));
 
-            for predicate in self.where_clause.predicates.iter() {
-                //do this later 
+                let typ = ty as &ToHtml<Style>;
+                typ.add_span_tokens(tokens);
             }
-        }
-
+        };
     }
 }
 
-impl<'a, Style> ToHtml<Style> for Declaration<&'a ast::Generics> where Style : ToCss {
+impl<Style> ToHtml<Style> for ast::TraitItem where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match *self {
+            RequiredMethod(ref method) => {
+                add_spans!(method.attrs.iter(), tokens);
 
-        let &Declaration(ref _self) = self;
-
-        if _self.lifetimes.len() == 0 && _self.ty_params.len() == 0 && _self.where_clause.predicates.len() == 0 {
-            return
-        }
-
-        add_token!(tokens, Operator, "<");
-
-        between_each!(lifetime , _self.lifetimes => {
-            add_token!(tokens, LifeTimeDecl, lifetime.lifetime.name.as_str());
-        } => {
-            add_token!(tokens, Operator, ",");            
-        });
+                add_token!(tokens, FnDecl, after_attrs(method.span, &method.attrs[]), Reach::Named(method.ident.name));
 
-        if _self.ty_params.len() > 0 && _self.lifetimes.len() > 0 {
-            add_token!(tokens, Operator, ",");            
-        }
+                let gen = &Declaration(&method.generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
 
-        between_each!(ty_param , _self.ty_params.as_slice() => {
-            add_token!(tokens, GenDecl, ty_param.ident.name.as_str());
-        } => {
-            add_token!(tokens, Operator, ",");            
-        });
+                let decl = &MethodDecl(&method.explicit_self.node, &method.decl) as &ToHtml<Style>;
+                decl.add_span_tokens(tokens);
 
-        add_token!(tokens, Operator, ">");
+                let where_clause = &method.generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
+            },
+            ProvidedMethod(ref method) => {
+                let method = method as &ToHtml<Style>;
+                method.add_span_tokens(tokens);
+            },
+            TypeTraitItem(ref assoc_ty) => {
+                add_spans!(assoc_ty.attrs.iter(), tokens);
 
-        // if _self.where_clause.predicates.len() > 0 {
-        //     add_token!(tokens, Keyword, "where");
-        // }
+                //a type parameter's span is only its name
+                add_token!(tokens, TyDecl, assoc_ty.ty_param.span);
 
-        // for predicate in _self.where_clause.predicates.iter() {
-        //     //do this later 
-        // }
+                let bounds = &Constraint(&assoc_ty.ty_param.bounds) as &ToHtml<Style>;
+                bounds.add_span_tokens(tokens);
+            }
+        };
     }
 }
 
-impl<Style> ToHtml<Style> for Box<ast::Ty> where Style : ToCss {
+impl<Style> ToHtml<Style> for ast::ImplItem where Style : ToCss {
     fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
-        match self.node {
-            TyVec(ref ty) => {
-                add_token!(tokens, Operator, "[");
-
-                let ty = ty as &ToHtml<Style>;
-                ty.add_span_tokens(tokens);
-
-                add_token!(tokens, Operator, "]");
+        match *self {
+            MethodImplItem(ref method) => {
+                let method = method as &ToHtml<Style>;
+                method.add_span_tokens(tokens);
             },
-            TyFixedLengthVec(ref ty, ref expr) => {
-                add_token!(tokens, Operator, "[");
+            TypeImplItem(ref typedef) => {
+                add_spans!(typedef.attrs.iter(), tokens);
 
-                let ty = ty as &ToHtml<Style>;
-                ty.add_span_tokens(tokens);
+                add_token!(tokens, TyDecl, after_attrs(typedef.span, &typedef.attrs[]), Reach::Named(typedef.ident.name));
 
-                //TODO:...this should be a ; with the new grammar change
-                add_token!(tokens, Operator, ";");
-                add_token!(tokens, Operator, "..");
-
-                //TODO:...make this a real call on the expr
-                add_token!(tokens, NumberLit, "4");
+                let typ = &typedef.typ as &ToHtml<Style>;
+                typ.add_span_tokens(tokens);
+            }
+        };
+    }
+}
 
-                add_token!(tokens, Operator, "]");
+impl<Style> ToHtml<Style> for Box<ast::Method> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.attrs.iter(), tokens);
 
-            },
-            TyPtr(ref mut_ty) => {
+        match self.node {
+            MethDecl(ref ident, ref generics, _, ref explicit_self, _, ref fn_decl, ref block, _) => {
+                add_token!(tokens, FnDecl, after_attrs(self.span, &self.attrs[]), Reach::Named(ident.name));
 
-            },
-            TyRptr(ref lifetime, ref mut_ty) => {
+                let gen = &Declaration(generics) as &ToHtml<Style>;
+                gen.add_span_tokens(tokens);
 
-            },
-            TyBareFn(ref bare_fn_ty) => {
+                let decl = &MethodDecl(&explicit_self.node, fn_decl) as &ToHtml<Style>;
+                decl.add_span_tokens(tokens);
 
-            },
-            TyTup(ref types) => {
+                let where_clause = &generics.where_clause as &ToHtml<Style>;
+                where_clause.add_span_tokens(tokens);
 
+                let body = block as &ToHtml<Style>;
+                body.add_span_tokens(tokens);
             },
-            TyPath(ref path, _) => {
-                if path.global {
-                    add_token!(tokens, Operator, "::");
-                }
-
-                let len = path.segments.len();
-                let segments = path.segments.iter();
-                for segment in segments.take(len - 1) {
-                    add_token!(tokens, Default, segment.identifier.as_str());
-                    add_token!(tokens, Operator, "::");
-                }
+            MethMac(ref mac) => {
+                let mac = mac as &ToHtml<Style>;
+                mac.add_span_tokens(tokens);
+            }
+        };
+    }
+}
 
-                let final_type = segments.last().unwrap();
-                add_token!(tokens, Type, final_type.identifier.as_str());
+impl<Style> ToHtml<Style> for ast::StructField where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.node.attrs.iter(), tokens);
 
-                match final_type.parameters {
-                    AngleBracketedParameters(ref data) => {
-                        let len = data.types.len();
+        match self.node.kind {
+            NamedField(ref ident, _) => {
+                add_token!(tokens, FieldDecl, after_attrs(self.span, &self.node.attrs[]), Reach::Named(ident.name));
+            },
+            UnnamedField(_) => {}
+        };
 
-                        if len == 0 {
-                            return;
-                        }
+        let ty = &self.node.ty as &ToHtml<Style>;
+        ty.add_span_tokens(tokens);
+    }
+}
 
-                        add_token!(tokens, Operator, "<");
-                        
-                        let types = data.types.iter();
-                        for ty in types.take(len - 1) {
-                            let ty = ty as &ToHtml<Style>;
-                            ty.add_span_tokens(tokens);
-                            add_token!(tokens, Operator, ",");
-                        }
+impl<Style> ToHtml<Style> for Box<ast::Variant> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.node.attrs.iter(), tokens);
 
-                        match types.last() {
-                            Some(ty) => {
-                                let ty = ty as &ToHtml<Style>;
-                                ty.add_span_tokens(tokens);
-                            },
-                            None => {}
-                        }
+        add_token!(tokens, VariantDecl, after_attrs(self.span, &self.node.attrs[]), Reach::Named(self.node.name.name));
 
-                        add_token!(tokens, Operator, ">");
-                    },
-                    _ => { /*TODO: handle the other case*/ }
+        match self.node.kind {
+            //A(B, C)
+            TupleVariantKind(ref args) => {
+                for arg in args.iter() {
+                    let ty = &arg.ty as &ToHtml<Style>;
+                    ty.add_span_tokens(tokens);
                 }
             },
-            TyObjectSum(ref ty, ref ty_param_bounds) => {
+            //A { b : B, c : C }
+            StructVariantKind(ref struct_def) => {
+                add_spans!(struct_def.fields.iter(), tokens);
+            }
+        };
 
+        match self.node.disr_expr {
+            Some(ref expr) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
             },
-            TyPolyTraitRef(ref ty_param_bounds) => {
+            None => {}
+        };
+    }
+}
 
-            },
-            TyQPath(ref qpath) => {
+impl<Style> ToHtml<Style> for Box<ast::FnDecl> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let decl = &MethodDecl(&SelfStatic, self) as &ToHtml<Style>;
+        decl.add_span_tokens(tokens);
+    }
+}
 
-            },
-            TyParen(ref ty) => {
+impl<'a, Style> ToHtml<Style> for MethodDecl<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &MethodDecl(explicit_self, decl) = self;
+
+        //the self argument is still the first of the decl's inputs
+        let inputs = match *explicit_self {
+            //`&'a mut self`, whose argument the parser makes up
+            SelfRegion(ref lifetime, _, _) => {
+                match *lifetime {
+                    Some(ref lifetime) => {
+                        add_token!(tokens, LifeTime, lifetime.span);
+                    },
+                    None => {}
+                };
 
+                &decl.inputs[1..]
             },
-            TyTypeof(ref expr) => {
+            _ => {
+                decl.inputs.as_slice()
+            }
+        };
 
-            },
-            TyInfer => {
+        add_spans!(inputs.iter(), tokens);
 
+        match decl.output {
+            Return(ref ty) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
             },
-        }
+            NoReturn(_) | DefaultReturn(_) => {}
+        };
     }
 }
 
-///////////////////////////////////////////////////////////////////////////////
-//                                                                           //
-//                   User src to ast type functions                          //                                      
-//                                                                           //
-///////////////////////////////////////////////////////////////////////////////
-
-pub fn str_to_crate(src : &str) -> ast::Crate {
-    // let mut parser = parser_from_str(src);
+impl<Style> ToHtml<Style> for ast::Arg where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        //`self` is a keyword rather than a parameter name
+        match self.pat.node {
+            PatIdent(_, ref ident, None) if ident.node.as_str() == "self" => {},
+            _ => {
+                let pat = &Binding(&self.pat, Class::Param) as &ToHtml<Style>;
+                pat.add_span_tokens(tokens);
+            }
+        };
 
-    let name = "".to_string();
-    
-    let handler    = syntax::diagnostic::default_handler(ColorConfig::Always, None);
-    let codemap    = syntax::codemap::CodeMap::new();
-    let filemap    = codemap.new_filemap(name, src.to_string());
-    let sp_handler = syntax::diagnostic::mk_span_handler(handler, codemap);
-    let reader     = StringReader::new(&sp_handler, filemap);
+        //`self`, `mut self` and untyped closure args carry an inferred type
+        match self.ty.node {
+            TyInfer => {},
+            _ => {
+                let ty = &self.ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            }
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::Generics where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        for lifetime in self.lifetimes.iter() {
+            add_token!(tokens, LifeTime, lifetime.lifetime.span);
+
+            let bounds = &LifetimeBounds(&lifetime.bounds) as &ToHtml<Style>;
+            bounds.add_span_tokens(tokens);
+        }
+
+        for ty_param in self.ty_params.iter() {
+            //a type parameter's span is only its name
+            add_token!(tokens, Gen, ty_param.span);
+
+            let bounds = &TyParamRest(ty_param) as &ToHtml<Style>;
+            bounds.add_span_tokens(tokens);
+        }
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for Declaration<&'a ast::Generics> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+
+        let &Declaration(ref _self) = self;
+
+        for lifetime in _self.lifetimes.iter() {
+            add_token!(tokens, LifeTimeDecl, lifetime.lifetime.span);
+
+            let bounds = &LifetimeBounds(&lifetime.bounds) as &ToHtml<Style>;
+            bounds.add_span_tokens(tokens);
+        }
+
+        for ty_param in _self.ty_params.iter() {
+            add_token!(tokens, GenDecl, ty_param.span);
+
+            let bounds = &TyParamRest(ty_param) as &ToHtml<Style>;
+            bounds.add_span_tokens(tokens);
+        }
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for LifetimeBounds<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &LifetimeBounds(bounds) = self;
+
+        for bound in bounds.iter() {
+            add_token!(tokens, LifeTime, bound.span);
+        }
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for TyParamRest<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &TyParamRest(ty_param) = self;
+
+        let bounds = &Constraint(&ty_param.bounds) as &ToHtml<Style>;
+        bounds.add_span_tokens(tokens);
+
+        match ty_param.default {
+            Some(ref ty) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            None => {}
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::WhereClause where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        for predicate in self.predicates.iter() {
+            match *predicate {
+                //T : Trait + 'a
+                BoundPredicate(ref bound) => {
+                    let ty = &bound.bounded_ty as &ToHtml<Style>;
+                    ty.add_span_tokens(tokens);
+
+                    let bounds = &Constraint(&bound.bounds) as &ToHtml<Style>;
+                    bounds.add_span_tokens(tokens);
+                },
+                //'a : 'b
+                RegionPredicate(ref region) => {
+                    add_token!(tokens, LifeTime, region.lifetime.span);
+
+                    let bounds = &LifetimeBounds(&region.bounds) as &ToHtml<Style>;
+                    bounds.add_span_tokens(tokens);
+                },
+                //T::Item = U
+                EqPredicate(ref eq) => {
+                    let path = &eq.path as &ToHtml<Style>;
+                    path.add_span_tokens(tokens);
+
+                    let ty = &eq.ty as &ToHtml<Style>;
+                    ty.add_span_tokens(tokens);
+                }
+            };
+        }
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for Constraint<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &Constraint(bounds) = self;
+
+        for bound in bounds.as_slice().iter() {
+            match *bound {
+                TraitTyParamBound(ref poly_trait_ref, _) => {
+                    let lifetimes = &HigherRanked(&poly_trait_ref.bound_lifetimes) as &ToHtml<Style>;
+                    lifetimes.add_span_tokens(tokens);
+
+                    let path = &TaggedPath(&poly_trait_ref.trait_ref.path, Class::GenConstraint) as &ToHtml<Style>;
+                    path.add_span_tokens(tokens);
+                },
+                RegionTyParamBound(ref lifetime) => {
+                    add_token!(tokens, LifeTime, lifetime.span);
+                }
+            };
+        }
+    }
+}
+
+impl<Style> ToHtml<Style> for Box<ast::Ty> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match self.node {
+            TyVec(ref ty) | TyParen(ref ty) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            TyFixedLengthVec(ref ty, ref expr) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+
+                //the old `[T; ..N]` form parses `..N` as a range
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            TyPtr(ref mut_ty) => {
+                let ty = &mut_ty.ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            TyRptr(ref lifetime, ref mut_ty) => {
+                match *lifetime {
+                    Some(ref lifetime) => {
+                        add_token!(tokens, LifeTime, lifetime.span);
+                    },
+                    None => {}
+                };
+
+                let ty = &mut_ty.ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            TyBareFn(ref bare_fn_ty) => {
+                let lifetimes = &HigherRanked(&bare_fn_ty.lifetimes) as &ToHtml<Style>;
+                lifetimes.add_span_tokens(tokens);
+
+                let decl = &bare_fn_ty.decl as &ToHtml<Style>;
+                decl.add_span_tokens(tokens);
+            },
+            TyTup(ref types) => {
+                add_spans!(types.iter(), tokens);
+            },
+            TyPath(ref path, _) => {
+                let path = path as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
+            },
+            //Box<Trait + Send>
+            TyObjectSum(ref ty, ref ty_param_bounds) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+
+                let bounds = ty_param_bounds as &ToHtml<Style>;
+                bounds.add_span_tokens(tokens);
+            },
+            TyPolyTraitRef(ref ty_param_bounds) => {
+                let bounds = ty_param_bounds as &ToHtml<Style>;
+                bounds.add_span_tokens(tokens);
+            },
+            //<T as Trait>::Assoc
+            TyQPath(ref qpath) => {
+                add_token!(tokens, Type, self.span, Reach::Last);
+
+                let qpath = qpath as &ToHtml<Style>;
+                qpath.add_span_tokens(tokens);
+            },
+            TyTypeof(ref expr) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            //the parser also makes these up, with spans that aren't theirs
+            TyInfer => {},
+        }
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for HigherRanked<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &HigherRanked(lifetimes) = self;
+
+        for lifetime in lifetimes.iter() {
+            add_token!(tokens, LifeTimeDecl, lifetime.lifetime.span);
+        }
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::Path where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let path = &TaggedPath(self, Class::Type) as &ToHtml<Style>;
+        path.add_span_tokens(tokens);
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for TaggedPath<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &TaggedPath(path, class) = self;
+
+        //the other segments are Default to the lexer already
+        if class != Class::Default {
+            tokens.push(SpanToken::new(class, path.span, Reach::Last));
+        }
+
+        for segment in path.segments.iter() {
+            let params = &segment.parameters as &ToHtml<Style>;
+            params.add_span_tokens(tokens);
+        }
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::PathParameters where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match *self {
+            //<'a, T, Item = U>
+            AngleBracketedParameters(ref data) => {
+                for lifetime in data.lifetimes.iter() {
+                    add_token!(tokens, LifeTime, lifetime.span);
+                }
+
+                add_spans!(data.types.iter(), tokens);
+
+                for binding in data.bindings.iter() {
+                    let ty = &binding.ty as &ToHtml<Style>;
+                    ty.add_span_tokens(tokens);
+                }
+            },
+            //Fn(A, B) -> C
+            ParenthesizedParameters(ref data) => {
+                add_spans!(data.inputs.iter(), tokens);
+
+                match data.output {
+                    Some(ref ty) => {
+                        let ty = ty as &ToHtml<Style>;
+                        ty.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            }
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::TraitRef where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let path = &self.path as &ToHtml<Style>;
+        path.add_span_tokens(tokens);
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::PolyTraitRef where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let lifetimes = &HigherRanked(&self.bound_lifetimes) as &ToHtml<Style>;
+        lifetimes.add_span_tokens(tokens);
+
+        let trait_ref = &self.trait_ref as &ToHtml<Style>;
+        trait_ref.add_span_tokens(tokens);
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::TyParamBound where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match *self {
+            TraitTyParamBound(ref poly_trait_ref, _) => {
+                let poly_trait_ref = poly_trait_ref as &ToHtml<Style>;
+                poly_trait_ref.add_span_tokens(tokens);
+            },
+            RegionTyParamBound(ref lifetime) => {
+                add_token!(tokens, LifeTime, lifetime.span);
+            }
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for ast::TyParamBounds where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.as_slice().iter(), tokens);
+    }
+}
+
+//The item's name is tagged by the type or expression around the QPath, as
+//the QPath has no span of its own
+impl<Style> ToHtml<Style> for Box<ast::QPath> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let self_type = &self.self_type as &ToHtml<Style>;
+        self_type.add_span_tokens(tokens);
+
+        let trait_ref = &*self.trait_ref as &ToHtml<Style>;
+        trait_ref.add_span_tokens(tokens);
+
+        let params = &self.item_path.parameters as &ToHtml<Style>;
+        params.add_span_tokens(tokens);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//                                                                           //
+//                   Expression implementations of ToHtml                    //
+//                                                                           //
+///////////////////////////////////////////////////////////////////////////////
+
+impl<Style> ToHtml<Style> for Box<ast::Expr> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match self.node {
+            ExprBox(ref place, ref expr) => {
+                match *place {
+                    Some(ref place) => {
+                        let place = place as &ToHtml<Style>;
+                        place.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            ExprVec(ref exprs) | ExprTup(ref exprs) => {
+                add_spans!(exprs.iter(), tokens);
+            },
+            ExprCall(ref callee, ref args) => {
+                match callee.node {
+                    ExprPath(ref path) => {
+                        let path = &TaggedPath(path, Class::FnCall) as &ToHtml<Style>;
+                        path.add_span_tokens(tokens);
+                    },
+                    _ => {
+                        let callee = callee as &ToHtml<Style>;
+                        callee.add_span_tokens(tokens);
+                    }
+                };
+
+                add_spans!(args.iter(), tokens);
+            },
+            //the receiver is the first of the args
+            ExprMethodCall(ref ident, ref types, ref args) => {
+                let receiver = &args[0] as &ToHtml<Style>;
+                receiver.add_span_tokens(tokens);
+
+                add_token!(tokens, FnCall, ident.span);
+
+                add_spans!(types.iter(), tokens);
+
+                add_spans!(args[1..].iter(), tokens);
+            },
+            ExprBinary(_, ref lhs, ref rhs) | ExprAssign(ref lhs, ref rhs) |
+            ExprAssignOp(_, ref lhs, ref rhs) | ExprIndex(ref lhs, ref rhs) => {
+                let lhs = lhs as &ToHtml<Style>;
+                lhs.add_span_tokens(tokens);
+
+                let rhs = rhs as &ToHtml<Style>;
+                rhs.add_span_tokens(tokens);
+            },
+            ExprUnary(_, ref expr) | ExprAddrOf(_, ref expr) | ExprParen(ref expr) |
+            ExprField(ref expr, _) | ExprTupField(ref expr, _) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            ExprLit(ref lit) => {
+                let lit = &**lit as &ToHtml<Style>;
+                lit.add_span_tokens(tokens);
+            },
+            ExprCast(ref expr, ref ty) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            ExprIf(ref cond, ref block, ref otherwise) => {
+                let cond = cond as &ToHtml<Style>;
+                cond.add_span_tokens(tokens);
+
+                let block = block as &ToHtml<Style>;
+                block.add_span_tokens(tokens);
+
+                //either another if or a plain block
+                match *otherwise {
+                    Some(ref otherwise) => {
+                        let otherwise = otherwise as &ToHtml<Style>;
+                        otherwise.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            ExprIfLet(ref pat, ref expr, ref block, ref otherwise) => {
+                let pat = pat as &ToHtml<Style>;
+                pat.add_span_tokens(tokens);
+
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+
+                let block = block as &ToHtml<Style>;
+                block.add_span_tokens(tokens);
+
+                match *otherwise {
+                    Some(ref otherwise) => {
+                        let otherwise = otherwise as &ToHtml<Style>;
+                        otherwise.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            //a loop's `'a:` label is a lifetime to the lexer
+            ExprWhile(ref cond, ref block, _) => {
+                let cond = cond as &ToHtml<Style>;
+                cond.add_span_tokens(tokens);
+
+                let block = block as &ToHtml<Style>;
+                block.add_span_tokens(tokens);
+            },
+            ExprWhileLet(ref pat, ref expr, ref block, _) | ExprForLoop(ref pat, ref expr, ref block, _) => {
+                let pat = pat as &ToHtml<Style>;
+                pat.add_span_tokens(tokens);
+
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+
+                let block = block as &ToHtml<Style>;
+                block.add_span_tokens(tokens);
+            },
+            ExprLoop(ref block, _) | ExprBlock(ref block) => {
+                let block = block as &ToHtml<Style>;
+                block.add_span_tokens(tokens);
+            },
+            ExprMatch(ref expr, ref arms, _) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+
+                add_spans!(arms.iter(), tokens);
+            },
+            ExprClosure(_, _, ref decl, ref block) => {
+                add_spans!(decl.inputs.iter(), tokens);
+
+                match decl.output {
+                    Return(ref ty) => {
+                        let ty = ty as &ToHtml<Style>;
+                        ty.add_span_tokens(tokens);
+                    },
+                    _ => {}
+                };
+
+                //`|x| x + 1` is parsed into a block that shares its expr's span
+                match block.expr {
+                    Some(ref expr) if block.stmts.len() == 0 && block.span == expr.span => {
+                        let expr = expr as &ToHtml<Style>;
+                        expr.add_span_tokens(tokens);
+                    },
+                    _ => {
+                        let block = block as &ToHtml<Style>;
+                        block.add_span_tokens(tokens);
+                    }
+                };
+            },
+            ExprRange(ref start, ref end) => {
+                match *start {
+                    Some(ref start) => {
+                        let start = start as &ToHtml<Style>;
+                        start.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+
+                match *end {
+                    Some(ref end) => {
+                        let end = end as &ToHtml<Style>;
+                        end.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            ExprPath(ref path) => {
+                let path = &TaggedPath(path, Class::Default) as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
+            },
+            //<T as Trait>::method
+            ExprQPath(ref qpath) => {
+                add_token!(tokens, Type, self.span, Reach::Last);
+
+                let qpath = qpath as &ToHtml<Style>;
+                qpath.add_span_tokens(tokens);
+            },
+            //their labels are lifetimes to the lexer
+            ExprBreak(_) | ExprAgain(_) => {},
+            ExprRet(ref expr) => {
+                add_token!(tokens, ReturnStmt, self.span, Reach::Named(keywords::Return.to_name()));
+
+                match *expr {
+                    Some(ref expr) => {
+                        let expr = &Returned(expr) as &ToHtml<Style>;
+                        expr.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            //only ever produced by expanding asm!, which we never do
+            ExprInlineAsm(_) => {},
+            ExprMac(ref mac) => {
+                let mac = mac as &ToHtml<Style>;
+                mac.add_span_tokens(tokens);
+            },
+            ExprStruct(ref path, ref fields, ref base) => {
+                let path = path as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
+
+                for field in fields.iter() {
+                    let expr = &field.expr as &ToHtml<Style>;
+                    expr.add_span_tokens(tokens);
+                }
+
+                match *base {
+                    Some(ref base) => {
+                        let base = base as &ToHtml<Style>;
+                        base.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            ExprRepeat(ref expr, ref count) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+
+                let count = count as &ToHtml<Style>;
+                count.add_span_tokens(tokens);
+            }
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for Box<ast::Block> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+
+        add_spans!(self.view_items.iter(), tokens);
+
+        add_spans!(self.stmts.iter(), tokens);
+
+        match self.expr {
+            Some(ref expr) => {
+                let expr = &Returned(expr) as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            None => {}
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for Box<ast::Stmt> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match self.node {
+            StmtDecl(ref decl, _) => {
+                match decl.node {
+                    DeclLocal(ref local) => {
+                        let local = local as &ToHtml<Style>;
+                        local.add_span_tokens(tokens);
+                    },
+                    DeclItem(ref item) => {
+                        let item = item as &ToHtml<Style>;
+                        item.add_span_tokens(tokens);
+                    }
+                };
+            },
+            StmtExpr(ref expr, _) | StmtSemi(ref expr, _) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            StmtMac(ref mac, _) => {
+                let mac = &**mac as &ToHtml<Style>;
+                mac.add_span_tokens(tokens);
+            }
+        };
+    }
+}
+
+impl<Style> ToHtml<Style> for Box<ast::Local> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let pat = &self.pat as &ToHtml<Style>;
+        pat.add_span_tokens(tokens);
+
+        match self.ty {
+            Some(ref ty) => {
+                let ty = ty as &ToHtml<Style>;
+                ty.add_span_tokens(tokens);
+            },
+            None => {}
+        };
+
+        match self.init {
+            Some(ref init) => {
+                let init = init as &ToHtml<Style>;
+                init.add_span_tokens(tokens);
+            },
+            None => {}
+        };
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for Returned<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &Returned(expr) = self;
+
+        //block-like expressions tag their own trailing expressions
+        match expr.node {
+            ExprIf(..) | ExprIfLet(..) | ExprMatch(..) | ExprBlock(..) |
+            ExprLoop(..) | ExprWhile(..) | ExprWhileLet(..) | ExprForLoop(..) => {},
+            //calls, literals, closures' bindings and so on keep their own classes
+            _ => {
+                add_token!(tokens, ReturnStmt, expr.span, Reach::Plain);
+            }
+        };
+
+        let ret = expr as &ToHtml<Style>;
+        ret.add_span_tokens(tokens);
+    }
+}
+
+impl<Style> ToHtml<Style> for Box<ast::Pat> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let pat = &Binding(self, Class::VarDecl) as &ToHtml<Style>;
+        pat.add_span_tokens(tokens);
+    }
+}
+
+impl<'a, Style> ToHtml<Style> for Binding<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &Binding(pat, class) = self;
+
+        match pat.node {
+            PatWild(_) => {},
+            //None and MAX, which parse just like new bindings
+            PatIdent(BindByValue(MutImmutable), ref ident, None) if existing_name(&ident.node).is_some() => {
+                let class = existing_name(&ident.node).unwrap();
+                tokens.push(SpanToken::new(class, ident.span, Reach::Named(ident.node.name)));
+            },
+            //the made up pattern of an unnamed argument, like the one in `fn(u8)`
+            PatIdent(_, ref ident, _) if ident.node.name == special_idents::invalid.name => {},
+            //a, ref a, mut a, a @ ...
+            PatIdent(_, ref ident, ref sub_pat) => {
+                //the ident's span may take in the `ref mut` of a struct field shorthand
+                tokens.push(SpanToken::new(class, ident.span, Reach::Named(ident.node.name)));
+
+                match *sub_pat {
+                    Some(ref sub_pat) => {
+                        let sub_pat = &Binding(sub_pat, class) as &ToHtml<Style>;
+                        sub_pat.add_span_tokens(tokens);
+                    },
+                    None => {}
+                };
+            },
+            //A(b, c), A(..) and a bare path like Ordering::Less
+            PatEnum(ref path, ref sub_pats) => {
+                let path = path as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
+
+                match *sub_pats {
+                    Some(ref sub_pats) => {
+                        for sub_pat in sub_pats.iter() {
+                            let sub_pat = &Binding(sub_pat, class) as &ToHtml<Style>;
+                            sub_pat.add_span_tokens(tokens);
+                        }
+                    },
+                    None => {}
+                };
+            },
+            //A { b, c : d, .. }
+            PatStruct(ref path, ref fields, _) => {
+                let path = path as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
+
+                for field in fields.iter() {
+                    let sub_pat = &Binding(&field.node.pat, class) as &ToHtml<Style>;
+                    sub_pat.add_span_tokens(tokens);
+                }
+            },
+            PatTup(ref sub_pats) => {
+                for sub_pat in sub_pats.iter() {
+                    let sub_pat = &Binding(sub_pat, class) as &ToHtml<Style>;
+                    sub_pat.add_span_tokens(tokens);
+                }
+            },
+            PatBox(ref sub_pat) | PatRegion(ref sub_pat, _) => {
+                let sub_pat = &Binding(sub_pat, class) as &ToHtml<Style>;
+                sub_pat.add_span_tokens(tokens);
+            },
+            PatLit(ref expr) => {
+                let expr = expr as &ToHtml<Style>;
+                expr.add_span_tokens(tokens);
+            },
+            PatRange(ref start, ref end) => {
+                let start = start as &ToHtml<Style>;
+                start.add_span_tokens(tokens);
+
+                let end = end as &ToHtml<Style>;
+                end.add_span_tokens(tokens);
+            },
+            //[a, b, rest.., c]
+            PatVec(ref before, ref slice, ref after) => {
+                for sub_pat in before.iter().chain(slice.iter()).chain(after.iter()) {
+                    let sub_pat = &Binding(sub_pat, class) as &ToHtml<Style>;
+                    sub_pat.add_span_tokens(tokens);
+                }
+            },
+            PatMac(ref mac) => {
+                let mac = mac as &ToHtml<Style>;
+                mac.add_span_tokens(tokens);
+            }
+        };
+    }
+}
 
-    let cfg = Vec::new();
-    let sess = new_parse_sess();
+//The class of an identifier pattern that names a unit variant, like `None`,
+//or a constant, like `MAX`, instead of binding something new. Only name
+//resolution could tell for sure, so this goes by the naming conventions.
+fn existing_name(ident : &ast::Ident) -> Option<Class> {
+    let name = ident.as_str();
 
-    Parser::new(&sess, cfg, box reader).parse_crate_mod()
+    if !name.chars().next().map_or(false, |c| c.is_uppercase()) {
+        return None;
+    }
 
-    // parser.parse_crate_mod()
+    if name.len() > 1 && name.chars().all(|c| c.is_uppercase() || c.is_digit(10) || c == '_') {
+        Some(Class::Default)
+    } else {
+        Some(Class::Type)
+    }
 }
 
-// pub fn str_to_expr(src : &str) -> Box<ast::Expr> {
-//     let mut parser = parser_from_str(src);
-//     parser.parse_expr()
-// }
+impl<Style> ToHtml<Style> for ast::Lit where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let class = match self.node {
+            LitStr(..) | LitBinary(..) | LitByte(..) | LitChar(..) => Class::StringLit,
+            LitInt(..) | LitFloat(..) | LitFloatUnsuffixed(..)      => Class::NumberLit,
+            LitBool(..)                                             => Class::BoolLit
+        };
+
+        //the value prints differently from how it was written, e.g. `0xff`,
+        //`1u8` or `r"\d"`, so the literal is placed by its span instead
+        tokens.push(SpanToken::new(class, self.span, Reach::All));
+    }
+}
 
-// pub fn str_to_stmt(src : &str) -> Box<ast::Stmt> {
-//     let mut parser = parser_from_str(src);
-//     parser.parse_stmt(Vec::new())
-// }
+impl<Style> ToHtml<Style> for ast::Arm where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        add_spans!(self.attrs.iter(), tokens);
 
-// pub fn str_to_item(src : &str) -> Box<ast::Item> {
-//     let mut parser = parser_from_str(src);
-//     parser.parse_item_with_outer_attributes().expect(
-//         "Tried to parse a source that is not an item as an item."
-//     )
-// }
+        add_spans!(self.pats.iter(), tokens);
 
-// pub fn str_to_view_item(src : &str) -> ast::ViewItem {
-//     let mut parser = parser_from_str(src);
-//     parser.parse_view_item(Vec::new())
-// }
+        match self.guard {
+            Some(ref guard) => {
+                let guard = guard as &ToHtml<Style>;
+                guard.add_span_tokens(tokens);
+            },
+            None => {}
+        };
 
-// pub fn parser_from_str(src : &str) -> Parser {
-//     let name = "".to_string();
-    
-//     let handler    = syntax::diagnostic::default_handler(ColorConfig::Always, None);
-//     let codemap    = syntax::codemap::CodeMap::new();
-//     let filemap    = codemap.new_filemap(name, src.to_string());
-//     let sp_handler = syntax::diagnostic::mk_span_handler(handler, codemap);
-//     let reader     = StringReader::new(&sp_handler, filemap);
+        let body = &self.body as &ToHtml<Style>;
+        body.add_span_tokens(tokens);
+    }
+}
 
-//     let cfg = Vec::new();
-//     let sess = new_parse_sess();
+impl<Style> ToHtml<Style> for ast::Mac where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match self.node {
+            MacInvocTT(ref path, ref tts, _) => {
+                let path = &TaggedPath(path, Class::MacCall) as &ToHtml<Style>;
+                path.add_span_tokens(tokens);
 
-//     Parser::new(&sess, cfg, box reader)
-// }
+                let tts = &TokenTrees(tts.as_slice(), false) as &ToHtml<Style>;
+                tts.add_span_tokens(tokens);
+            }
+        };
+    }
+}
 
 ///////////////////////////////////////////////////////////////////////////////
 //                                                                           //
-//                   Whitespace restoration in tokens                        //                                      
+//                   Token tree implementations of ToHtml                    //
 //                                                                           //
 ///////////////////////////////////////////////////////////////////////////////
 
-fn restore_whitespace(original : &str, tokens : &mut Vec<SpanToken>) {
-    let mut original = original;
+impl<'a, Style> ToHtml<Style> for TokenTrees<'a> where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &TokenTrees(tts, matcher) = self;
+
+        let mut i = 0;
+        while i < tts.len() {
+            match (&tts[i], tts.get(i + 1)) {
+                //unexpanded macro_rules! bodies keep `$x:expr` as plain tokens
+                (&TtToken(dollar, token::Dollar), Some(&TtToken(name, token::Ident(..)))) => {
+                    add_token!(tokens, MacParam, dollar);
+                    add_token!(tokens, MacParam, name);
+                    i += 2;
+
+                    //anywhere else `$x: u8` is a param and then a type
+                    if !matcher {
+                        continue;
+                    }
+
+                    match (tts.get(i), tts.get(i + 1)) {
+                        (Some(&TtToken(_, token::Colon)), Some(&TtToken(kind, token::Ident(..)))) => {
+                            add_token!(tokens, MacParam, kind);
+                            i += 2;
+                        },
+                        _ => {}
+                    };
+                },
+                //nested trees are as much a matcher as the run they're in
+                (&TtDelimited(_, ref delimited), _) => {
+                    let tts = &TokenTrees(delimited.tts.as_slice(), matcher) as &ToHtml<Style>;
+                    tts.add_span_tokens(tokens);
+
+                    i += 1;
+                },
+                (&TtSequence(_, ref seq), _) => {
+                    let tts = &TokenTrees(seq.tts.as_slice(), matcher) as &ToHtml<Style>;
+                    tts.add_span_tokens(tokens);
+
+                    i += 1;
+                },
+                (tt, _) => {
+                    let tt = tt as &ToHtml<Style>;
+                    tt.add_span_tokens(tokens);
+
+                    i += 1;
+                }
+            };
+        }
+    }
+}
 
-    for token in tokens.iter_mut() {
-        let index = match original.find_str(&token.text[]) {
-            Some(i) => i,
-            None    => panic!("When trying to restore whitespace, the token {} wasn't found in the original text", token)
+impl<Style> ToHtml<Style> for ast::TokenTree where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        match *self {
+            //$x:expr and $x in an already quoted tree
+            TtToken(span, token::MatchNt(..)) | TtToken(span, token::SubstNt(..)) => {
+                add_token!(tokens, MacParam, span);
+            },
+            //any other token is classed by the lexer
+            TtToken(..) => {},
+            TtDelimited(_, ref delimited) => {
+                let tts = &TokenTrees(delimited.tts.as_slice(), false) as &ToHtml<Style>;
+                tts.add_span_tokens(tokens);
+            },
+            //$(...),*
+            TtSequence(_, ref seq) => {
+                let tts = &TokenTrees(seq.tts.as_slice(), false) as &ToHtml<Style>;
+                tts.add_span_tokens(tokens);
+            }
         };
-        
-        let text_len = token.text.len();
-        
-        let mut whitespace = original[0..index].to_string();
-        whitespace.push_str(&token.text[]);
-        token.text = whitespace;
-        
-        original = &original[(index + text_len)..];
     }
 }
 
-fn main() {
-    let src = "
-    #[phase(plugin)]
-    extern crate highlightrs;
+//Source highlighted without parsing it. It marks nothing, so every one of
+//its source tokens is classed by token_class alone
+struct Unparsed;
+
+impl<Style> ToHtml<Style> for Unparsed where Style : ToCss {
+    fn add_span_tokens(&self, _ : &mut Vec<SpanToken>) {}
+}
+
+//A program that only parsed up to an error: what parsed of the source before
+//the cut, with its delimiters closed, and the span and message of the error.
+//The source after the cut is left to the lexer.
+struct Recovered(ast::Crate, usize, Span, String);
+
+impl<Style> ToHtml<Style> for Recovered where Style : ToCss {
+    fn add_span_tokens(&self, tokens : &mut Vec<SpanToken>) {
+        let &Recovered(ref crayt, cut, _, _) = self;
+
+        let mut marks = Vec::new();
+
+        let crayt = crayt as &ToHtml<Style>;
+        crayt.add_span_tokens(&mut marks);
+
+        //the closers added at the cut aren't in the source, so nothing reaches past it
+        for mut mark in marks.into_iter() {
+            if mark.span.lo.to_usize() >= cut {
+                continue;
+            }
+
+            if mark.span.hi.to_usize() > cut {
+                mark.span.hi = BytePos::from_usize(cut);
+            }
+
+            tokens.push(mark);
+        }
+    }
+
+    fn error(&self) -> Option<(Span, &str)> {
+        let &Recovered(_, _, span, ref message) = self;
+        Some((span, &message[]))
+    }
+}
+
+//How a lone token is highlighted when there is no AST around it
+fn token_class(tok : &token::Token) -> Class {
+    match *tok {
+        token::Literal(token::Integer(_), _) | token::Literal(token::Float(_), _) => {
+            Class::NumberLit
+        },
+        token::Literal(..) => {
+            Class::StringLit
+        },
+        token::Ident(..) if tok.is_keyword(keywords::True) || tok.is_keyword(keywords::False) => {
+            Class::BoolLit
+        },
+        token::Ident(..) if tok.is_any_keyword() => {
+            Class::Keyword
+        },
+        //`_` in a pattern or a type
+        token::Underscore => {
+            Class::Keyword
+        },
+        token::Ident(..) | token::Interpolated(..) => {
+            Class::Default
+        },
+        token::Lifetime(..) => {
+            Class::LifeTime
+        },
+        token::DocComment(..) => {
+            Class::DocComment
+        },
+        token::Comment => {
+            Class::Comment
+        },
+        token::MatchNt(..) | token::SubstNt(..) => {
+            Class::MacParam
+        },
+        _ => {
+            Class::Operator
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//                                                                           //
+//                   Errors                                                  //
+//                                                                           //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    //the parser rejected the source
+    Syntax,
+    //the source parsed, but not as the fragment it was highlighted as
+    WrongFragment,
+    //stripping the markup from the output didn't give back the source
+    RoundTrip,
+    //the parser or the lexer panicked without reporting why
+    Internal
+}
+
+#[derive(Show, Clone)]
+pub struct HighlightError {
+    pub kind    : ErrorKind,
+    pub message : String,
+    //byte offsets into the source, when the error has a location
+    pub span    : Option<Span>
+}
+
+impl fmt::String for HighlightError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}..{}: {}", span.lo.to_usize(), span.hi.to_usize(), self.message),
+            None       => write!(f, "{}", self.message)
+        }
+    }
+}
+
+type Diagnostics = Arc<Mutex<Vec<(Option<Span>, String)>>>;
+
+//Keeps the errors the parser and lexer report instead of printing them
+struct Captured(Diagnostics);
+
+impl diagnostic::Emitter for Captured {
+    fn emit(&mut self, cmsp : Option<(&CodeMap, Span)>, msg : &str, _ : Option<&str>, lvl : diagnostic::Level) {
+        match lvl {
+            diagnostic::Level::Bug | diagnostic::Level::Fatal | diagnostic::Level::Error => {
+                let Captured(ref diagnostics) = *self;
+                diagnostics.lock().unwrap().push((cmsp.map(|(_, span)| span), msg.to_string()));
+            },
+            _ => {}
+        };
+    }
+
+    fn custom_emit(&mut self, cm : &CodeMap, sp : diagnostic::RenderSpan, msg : &str, lvl : diagnostic::Level) {
+        self.emit(Some((cm, sp.span())), msg, None, lvl);
+    }
+}
+
+fn capturing_handler(diagnostics : Diagnostics) -> diagnostic::SpanHandler {
+    let handler = diagnostic::mk_handler(false, box Captured(diagnostics));
+    diagnostic::mk_span_handler(handler, CodeMap::new())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//                                                                           //
+//                   User src to ast type functions                          //                                      
+//                                                                           //
+///////////////////////////////////////////////////////////////////////////////
+
+fn parse_crate(parser : &mut Parser) -> Option<ast::Crate> {
+    Some(parser.parse_crate_mod())
+}
+
+//The fragment parsers give back None when src holds more than one fragment
+
+fn parse_expr(parser : &mut Parser) -> Option<Box<ast::Expr>> {
+    let expr = parser.parse_expr();
+    all_parsed(parser, expr)
+}
+
+fn parse_stmt(parser : &mut Parser) -> Option<Box<ast::Stmt>> {
+    let stmt = parser.parse_stmt(Vec::new());
+
+    //parse_stmt leaves the semicolon for the enclosing block
+    parser.eat(&token::Semi);
+
+    all_parsed(parser, stmt)
+}
+
+fn parse_item(parser : &mut Parser) -> Option<Box<ast::Item>> {
+    parser.parse_item_with_outer_attributes().and_then(|item| all_parsed(parser, item))
+}
+
+fn parse_ty(parser : &mut Parser) -> Option<Box<ast::Ty>> {
+    let ty = parser.parse_ty_sum();
+    all_parsed(parser, ty)
+}
+
+fn parse_pat(parser : &mut Parser) -> Option<Box<ast::Pat>> {
+    let pat = parser.parse_pat();
+    all_parsed(parser, pat)
+}
+
+fn all_parsed<T>(parser : &mut Parser, fragment : T) -> Option<T> {
+    if parser.token == token::Eof {
+        Some(fragment)
+    } else {
+        None
+    }
+}
+
+//Parses src with parse and renders what it gives back with render. `fragment`
+//names what parse expects, for when src parses but isn't one.
+fn with_parser<T, P, R>(src : &str, fragment : &str, parse : P, render : R) -> Result<String, HighlightError>
+    where P : FnOnce(&mut Parser) -> Option<T> + Send, R : FnOnce(T) -> String + Send {
+    guarded(fragment, move |captured| {
+        let sess    = new_parse_sess_special_handler(capturing_handler(captured));
+        let filemap = sess.span_diagnostic.cm.new_filemap("".to_string(), src.to_string());
+        let reader  = InlineMods::new(StringReader::new(&sess.span_diagnostic, filemap));
+
+        let cfg = Vec::new();
+
+        let mut parser = Parser::new(&sess, cfg, box reader);
+
+        let parsed = parse(&mut parser);
+
+        //the parser recovers from some errors, but what it recovered isn't src
+        if sess.span_diagnostic.handler().has_errors() {
+            None
+        } else {
+            parsed.map(render)
+        }
+    })
+}
+
+//Reads src like the StringReader it wraps, except that `mod a;` comes out as
+//`mod a {}`. The parser would otherwise load a.rs, or whatever file a
+//`#[path]` names, from disk.
+struct InlineMods<'a> {
+    reader  : StringReader<'a>,
+    //the last two tokens that weren't whitespace or comments
+    last    : (token::Token, token::Token),
+    //the `}` owed to a `mod a;` whose `;` was read as `{`
+    closing : Option<TokenAndSpan>
+}
+
+impl<'a> InlineMods<'a> {
+    fn new(reader : StringReader<'a>) -> InlineMods<'a> {
+        InlineMods {
+            reader  : reader,
+            last    : (token::Eof, token::Eof),
+            closing : None
+        }
+    }
+
+    //The token as the parser should see it: a `;` ending `mod a` opens the
+    //module's body instead
+    fn inlined(&self, mut next : TokenAndSpan) -> TokenAndSpan {
+        let (ref before, ref name) = self.last;
+
+        if next.tok == token::Semi && before.is_keyword(keywords::Mod) && name.is_plain_ident() {
+            next.tok = token::OpenDelim(token::Brace);
+        }
+
+        next
+    }
+}
+
+impl<'a> Reader for InlineMods<'a> {
+    fn is_eof(&self) -> bool {
+        self.closing.is_none() && self.reader.is_eof()
+    }
+
+    fn next_token(&mut self) -> TokenAndSpan {
+        if let Some(closing) = self.closing.take() {
+            return closing;
+        }
+
+        let next = self.reader.next_token();
+
+        match next.tok {
+            token::Whitespace | token::Comment | token::Shebang(_) => { return next; },
+            _ => {}
+        };
+
+        let semi = next.tok == token::Semi;
+        let next = self.inlined(next);
+
+        //the body is closed right away, with nothing in it
+        if semi && next.tok == token::OpenDelim(token::Brace) {
+            self.closing = Some(TokenAndSpan {
+                tok : token::CloseDelim(token::Brace),
+                sp  : codemap::mk_sp(next.sp.hi, next.sp.hi)
+            });
+        }
+
+        self.last = (self.last.1.clone(), next.tok.clone());
+
+        next
+    }
+
+    fn fatal(&self, msg : &str) -> ! {
+        self.reader.fatal(msg)
+    }
+
+    fn err(&self, msg : &str) {
+        self.reader.err(msg)
+    }
+
+    fn peek(&self) -> TokenAndSpan {
+        match self.closing {
+            Some(ref closing) => closing.clone(),
+            None              => self.inlined(self.reader.peek())
+        }
+    }
+}
+
+//Checks that all of src lexes, then renders it with render. Only the errors
+//the lexer can't go on from count, since the tokens it recovers still cover
+//the whole source.
+fn with_lexer<R>(src : &str, render : R) -> Result<String, HighlightError> where R : FnOnce() -> String + Send {
+    guarded("token stream", move |captured| {
+        lex_with(src, &capturing_handler(captured));
+        Some(render())
+    })
+}
+
+//Runs attempt on a thread of its own, since the parser and the lexer panic
+//when they give up, and turns the first error they reported into the
+//HighlightError. When attempt gives back None without reporting anything, src
+//was valid but not the `fragment` it was taken for, and when it panics
+//without reporting anything, that's a bug of its own.
+fn guarded<T, F>(fragment : &str, attempt : F) -> Result<T, HighlightError>
+    where T : Send, F : FnOnce(Diagnostics) -> Option<T> + Send {
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let captured    = diagnostics.clone();
+
+    //the thread's panic message would go to the real stderr otherwise
+    let rendered = Builder::new().stderr(box NullWriter).scoped(move || attempt(captured)).join();
+
+    let panicked = match rendered {
+        Ok(Some(rendered)) => { return Ok(rendered); },
+        Ok(None)           => false,
+        Err(_)             => true
+    };
+
+    let diagnostics = diagnostics.lock().unwrap();
+
+    Err(match (diagnostics.first(), panicked) {
+        (Some(&(span, ref message)), _) => HighlightError {
+            kind    : ErrorKind::Syntax,
+            message : message.clone(),
+            span    : span
+        },
+        (None, false) => HighlightError {
+            kind    : ErrorKind::WrongFragment,
+            message : format!("the source is not one {}", fragment),
+            span    : None
+        },
+        (None, true) => HighlightError {
+            kind    : ErrorKind::Internal,
+            message : format!("panicked while reading the source as one {}", fragment),
+            span    : None
+        }
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//                                                                           //
+//                   Classifying source tokens                               //
+//                                                                           //
+///////////////////////////////////////////////////////////////////////////////
+
+//Gives every source token a class: the one token_class gives it, unless one
+//of the AST's marks reaches it. Marks go in the order the AST made them, so
+//a node's children win over the node, and Plain marks go last. Comments are
+//never marked, as the AST doesn't know about them.
+fn classify(original : &str, marks : Vec<SpanToken>) -> Vec<SpanToken> {
+    let lexed : Vec<TokenAndSpan> = lex(original).into_iter().filter(|lexed| {
+        lexed.tok != token::Whitespace
+    }).collect();
+
+    let mut tokens : Vec<SpanToken> = lexed.iter().map(|lexed| {
+        SpanToken::new(token_class(&lexed.tok), lexed.sp, Reach::All)
+    }).collect();
+
+    let (plain, marks) : (Vec<SpanToken>, Vec<SpanToken>) = marks.into_iter().partition(|mark| {
+        mark.reach == Reach::Plain
+    });
+
+    for mark in marks.iter().chain(plain.iter()) {
+        for i in reached(&lexed[], &tokens[], mark).into_iter() {
+            tokens[i].class = mark.class;
+        }
+    }
+
+    tokens
+}
+
+//The indices of the tokens a mark gives its class to
+fn reached(lexed : &[TokenAndSpan], tokens : &[SpanToken], mark : &SpanToken) -> Vec<usize> {
+    let lo = mark.span.lo.to_usize();
+    let hi = mark.span.hi.to_usize();
+
+    //the tokens are in order and don't overlap, so those in the span are a run
+    let within : Vec<usize> = (first_from(lexed, lo)..lexed.len()).take_while(|&i| {
+        lexed[i].sp.hi.to_usize() <= hi
+    }).filter(|&i| {
+        !is_comment(&lexed[i].tok)
+    }).collect();
+
+    match mark.reach {
+        Reach::All => within,
+        Reach::Named(name) => {
+            within.into_iter().filter(|&i| {
+                match lexed[i].tok {
+                    token::Ident(ident, _) => ident.name == name,
+                    _                      => false
+                }
+            }).take(1).collect()
+        },
+        Reach::Last => {
+            last_segment(lexed, within).into_iter().collect()
+        },
+        Reach::Plain => {
+            within.into_iter().filter(|&i| tokens[i].class == Class::Default).collect()
+        }
+    }
+}
+
+//The index of the first token starting at or after pos
+fn first_from(lexed : &[TokenAndSpan], pos : usize) -> usize {
+    let mut lo = 0;
+    let mut hi = lexed.len();
+
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+
+        if lexed[mid].sp.lo.to_usize() < pos {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    lo
+}
+
+//The last identifier outside of any brackets, up to the `->` of `Fn(A) -> B`
+fn last_segment(lexed : &[TokenAndSpan], within : Vec<usize>) -> Option<usize> {
+    let mut depth = 0i32;
+    let mut last  = None;
+
+    for i in within.into_iter() {
+        match lexed[i].tok {
+            token::OpenDelim(_) | token::Lt     => { depth += 1; },
+            token::CloseDelim(_) | token::Gt    => { depth -= 1; },
+            //`Vec<<T as Trait>::A>` and `Vec<Vec<T>>`
+            token::BinOp(token::Shl)            => { depth += 2; },
+            token::BinOp(token::Shr)            => { depth -= 2; },
+            token::RArrow if depth == 0         => { break; },
+            token::Ident(..) if depth == 0 && !lexed[i].tok.is_any_keyword() => {
+                last = Some(i);
+            },
+            _                                   => {}
+        };
+    }
+
+    last
+}
+
+fn is_comment(tok : &token::Token) -> bool {
+    match *tok {
+        token::Comment | token::DocComment(_) => true,
+        _                                     => false
+    }
+}
+
+//The part of an item's span after its outer attributes, where its name is
+//looked for
+fn after_attrs(span : Span, attrs : &[ast::Attribute]) -> Span {
+    let lo = attrs.iter().filter(|attr| attr.node.style == AttrOuter).fold(span.lo.to_usize(), |lo, attr| {
+        cmp::max(lo, attr.span.hi.to_usize())
+    });
+
+    codemap::mk_sp(BytePos::from_usize(lo), span.hi)
+}
+
+//Replaces the tokens the span touches with one Error token covering all of
+//them, titled with the message. An empty span marks the token right after it.
+fn mark_error(tokens : Vec<SpanToken>, span : Span, message : &str) -> Vec<SpanToken> {
+    let lo = span.lo.to_usize();
+    let hi = span.hi.to_usize();
+
+    let first = match tokens.iter().position(|token| token.span.hi.to_usize() > lo) {
+        Some(first)                 => first,
+        None if tokens.len() > 0    => tokens.len() - 1,
+        None                        => { return tokens; }
+    };
+
+    let last = match tokens.iter().rposition(|token| token.span.lo.to_usize() < hi) {
+        Some(last) => cmp::max(first, last),
+        None       => first
+    };
+
+    let error_lo = tokens[first].span.lo.to_usize();
+    let error_hi = tokens[last].span.hi.to_usize();
+
+    let mut marked = Vec::with_capacity(tokens.len() - (last - first));
+
+    for (i, token) in tokens.into_iter().enumerate() {
+        if i == first {
+            let mut error = SpanToken::new(Class::Error, mk_span(error_lo, error_hi), Reach::All);
+            error.title = Some(message.to_string());
+
+            marked.push(error);
+        } else if i < first || i > last {
+            marked.push(token);
+        }
+    }
+
+    marked
+}
+
+//How many prefixes tolerant parses before it settles for the empty one. Each
+//is a parse of its own, so trying every statement would be quadratic.
+const RECOVERY_ATTEMPTS : usize = 8;
+
+//The offsets in src before lo at which a program cut short may still parse,
+//each with the braces that close what is open there: after every `;`, `{` and
+//`}` outside of parentheses and brackets, i.e. between items and statements.
+//Latest first, at most RECOVERY_ATTEMPTS of them, and always ending with the
+//start. Lexes on a thread of its own since the lexer panics when it gives up.
+fn recovery_points(src : &str, lo : usize) -> Result<Vec<(usize, String)>, HighlightError> {
+    guarded("token stream", move |captured| {
+        let mut points = Vec::new();
+        let mut open   = Vec::new();
+
+        for lexed in lex_with(src, &capturing_handler(captured)).iter() {
+            if lexed.sp.hi.to_usize() > lo {
+                break;
+            }
+
+            match lexed.tok {
+                token::OpenDelim(delim) => { open.push(delim); },
+                token::CloseDelim(_)    => { open.pop(); },
+                token::Semi             => {},
+                _                       => { continue; }
+            };
+
+            if open.iter().any(|&delim| delim != token::Brace) {
+                continue;
+            }
+
+            let closers = open.iter().map(|_| '}').collect();
+
+            points.push((lexed.sp.hi.to_usize(), closers));
+        }
+
+        points.reverse();
+        points.truncate(RECOVERY_ATTEMPTS - 1);
+        points.push((0, String::new()));
+
+        Some(points)
+    })
+}
+
+//Splits src into its byte order mark, if it has one, and the rest, which is
+//what the codemap keeps and so what spans count from
+fn split_bom(src : &str) -> (&str, &str) {
+    if src.starts_with("\u{feff}") {
+        ("\u{feff}", &src[3..])
+    } else {
+        ("", src)
+    }
+}
+
+fn mk_span(lo : usize, hi : usize) -> Span {
+    codemap::mk_sp(BytePos::from_usize(lo), BytePos::from_usize(hi))
+}
+
+//Every token of src, including whitespace and comments
+fn lex(src : &str) -> Vec<TokenAndSpan> {
+    lex_with(src, &capturing_handler(Arc::new(Mutex::new(Vec::new()))))
+}
+
+fn lex_with(src : &str, sp_handler : &diagnostic::SpanHandler) -> Vec<TokenAndSpan> {
+    let filemap    = sp_handler.cm.new_filemap("".to_string(), src.to_string());
+    let mut reader = StringReader::new(sp_handler, filemap);
+
+    let mut lexed = Vec::new();
+
+    loop {
+        let next = reader.next_token();
+
+        if next.tok == token::Eof {
+            break;
+        }
+
+        lexed.push(next);
+    }
+
+    lexed
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//                                                                           //
+//                   Round trip verification                                 //
+//                                                                           //
+///////////////////////////////////////////////////////////////////////////////
+
+//Escapes text for use both between tags and inside a double or single
+//quoted attribute value
+fn escape(text : &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&'  => escaped.push_str("&amp;"),
+            '<'  => escaped.push_str("&lt;"),
+            '>'  => escaped.push_str("&gt;"),
+            '"'  => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _    => escaped.push(c)
+        };
+    }
+
+    escaped
+}
+
+//The text of highlighted html with every tag removed and escapes undone
+pub fn strip_markup(html : &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<'            => { in_tag = true; },
+            '>' if in_tag  => { in_tag = false; },
+            _ if in_tag    => {},
+            _              => { text.push(c); }
+        };
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
 
-    use highlightrs::{string_to_crate, string_to_item, string_to_view_item};
-    use highlightrs::style::themes::Monokai;
-    use highlightrs::highlight::*;
-    use syntax::ptr;
-    pub use highlightrs::Highlited;
+//The first byte offset at which the highlighted html stops matching src
+fn round_trip_mismatch(src : &str, html : &str) -> Option<usize> {
+    let text = strip_markup(html);
 
-    type Box<'l, T> = ptr::Ptr<'l, T>;
-    type BorrowedBox<'a, 'b, 'c, T1, T2> = ptr::BorrowedPtr<'a, 'b, 'c, T1, T2>;
-    type FourPtrs<T> = [Box<T>; ..4];
-    type Ptrs = [Box<T>];
+    if &text[] == src {
+        return None;
+    }
 
-    ";
+    let same = src.bytes().zip(text.bytes()).take_while(|&(a, b)| a == b).count();
 
-    println!("{}", ::highlight::program(src, DefaultInline));
+    Some(same)
 }
@@ -0,0 +1,10 @@
+#![feature(slicing_syntax)]
+#![feature(box_syntax)]
+
+extern crate syntax;
+
+pub use html_highlighter::{highlight, strip_markup};
+pub use html_highlighter::{ToCss, Class, StyleSheet, DefaultInline};
+pub use html_highlighter::{HighlightError, ErrorKind};
+
+mod html_highlighter;
@@ -1,15 +1,15 @@
-#![feature(slicing_syntax)] 
+#![feature(slicing_syntax)]
 
 extern crate getopts;
-extern crate rustdoc;
-extern crate syntax;
+extern crate highlightrs;
 
 use getopts::{optopt, optflag, getopts, usage};
 
-use rustdoc::html::highlight::highlight;
+use highlightrs::{highlight, DefaultInline};
 
 use std::io::fs::File;
 use std::io::{FileMode, FileAccess};
+use std::io::stdio;
 use std::os;
 
 fn main() {
@@ -18,24 +18,37 @@ fn main() {
     let opts = &[
         optopt("i", "inputfile", "use a file for the input", "FILE"),
         optopt("o", "outfile", "use a file for the output", "FILE"),
+        optflag("v", "verify", "check that the output strips back to the input"),
         optflag("h", "help", "print this help menu")
     ];
 
     let matches = match getopts(args.tail(), opts) {
         Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => { return fail(f.to_string()); }
     };
 
     if matches.opt_present("h") {
-        println!("{}", usage("", opts));
+        println!("{}", usage("Usage: highlightrs [options] [SOURCE]\n       highlightrs -v FILE...", opts));
+        return;
+    }
+
+    //checks every file given instead of highlighting one input
+    if matches.opt_present("v") && matches.opt_str("i").is_none() && matches.free.len() > 0 {
+        for path in matches.free.iter() {
+            match verify(&path[]) {
+                Ok(())   => println!("ok       {}", path),
+                Err(msg) => fail(format!("failed   {}: {}", path, msg))
+            };
+        }
+
         return;
     }
 
     let input =  match matches.opt_str("i"){
         Some(file) => {
-            match File::open(&Path::new(file)).read_to_string() {
+            match File::open(&Path::new(&file[])).read_to_string() {
                 Ok(src) => src,
-                Err(e) => panic!(e)
+                Err(e) => { return fail(format!("couldn't read {}: {}", file, e)); }
             }
         },
         None => {
@@ -47,18 +60,47 @@ fn main() {
         }
     };
 
-    let html = highlight(input[], None, None);
+    let highlighted = if matches.opt_present("v") {
+        highlight::verified_program(&input[], DefaultInline)
+    } else {
+        highlight::auto(&input[], DefaultInline)
+    };
+
+    let html = match highlighted {
+        Ok(html) => html,
+        Err(e) => { return fail(format!("couldn't highlight the input: {}", e)); }
+    };
 
     match matches.opt_str("o"){
         Some(file) => {
-            let mut out_file = File::open_mode(&Path::new(file), FileMode::Open, FileAccess::Write);
-            match out_file.write_str(html[]) {
+            let mut out_file = File::open_mode(&Path::new(&file[]), FileMode::Open, FileAccess::Write);
+            match out_file.write_str(&html[]) {
                 Ok(_) => {},
-                Err(e) => { panic!(e); }
+                Err(e) => { return fail(format!("couldn't write {}: {}", file, e)); }
             };
         },
         None => {
             println!("{}", html);
         }
     };
-}
\ No newline at end of file
+}
+
+//Highlights the file at path as a program and checks that it strips back to
+//what was read
+fn verify(path : &str) -> Result<(), String> {
+    let src = match File::open(&Path::new(path)).read_to_string() {
+        Ok(src) => src,
+        Err(e)  => { return Err(format!("couldn't read it: {}", e)); }
+    };
+
+    match highlight::verified_program(&src[], DefaultInline) {
+        Ok(_)  => Ok(()),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+//Reports msg on stderr and makes the process exit unsuccessfully
+fn fail(msg : String) {
+    let _ = stdio::stderr().write_line(&msg[]);
+    os::set_exit_status(1);
+}
@@ -0,0 +1,178 @@
+#![feature(slicing_syntax)]
+
+extern crate highlightrs;
+
+use highlightrs::{highlight, ToCss, Class};
+
+//Puts the name of each token's class in its class attribute
+#[derive(Clone)]
+struct ClassNames;
+
+impl ToCss for ClassNames {
+    fn to_css(self, clazz : &Class) -> String {
+        format!("{:?}", clazz)
+    }
+}
+
+//Whether text is highlighted with class somewhere in the item src
+fn item_has(src : &str, class : &str, text : &str) -> bool {
+    let html = highlight::item(src, ClassNames).unwrap();
+    html.contains(&format!("<span class=\"{}\">{}</span>", class, text)[])
+}
+
+//Whether text is highlighted with class somewhere in the expression src
+fn expr_has(src : &str, class : &str, text : &str) -> bool {
+    let html = highlight::expr(src, ClassNames).unwrap();
+    html.contains(&format!("<span class=\"{}\">{}</span>", class, text)[])
+}
+
+//Whether text is highlighted with class somewhere in the type src
+fn ty_has(src : &str, class : &str, text : &str) -> bool {
+    let html = highlight::ty(src, ClassNames).unwrap();
+    html.contains(&format!("<span class=\"{}\">{}</span>", class, text)[])
+}
+
+#[test]
+fn fn_items() {
+    let src = "fn foo(a : u8) -> u8 { a }";
+
+    assert!(item_has(src, "FnDecl", "foo"));
+    assert!(item_has(src, "Param", "a"));
+}
+
+#[test]
+fn structs() {
+    let src = "struct Point { x : i32, pub y : i32 }";
+
+    assert!(item_has(src, "TyDecl", "Point"));
+    assert!(item_has(src, "FieldDecl", "x"));
+    assert!(item_has(src, "FieldDecl", "y"));
+}
+
+#[test]
+fn enums() {
+    let src = "enum Shape { Dot, Circle(f64) }";
+
+    assert!(item_has(src, "TyDecl", "Shape"));
+    assert!(item_has(src, "VariantDecl", "Dot"));
+    assert!(item_has(src, "VariantDecl", "Circle"));
+}
+
+#[test]
+fn traits() {
+    let src = "trait Shape { fn area(&self) -> f64; fn name(&self) -> u8 { 0 } }";
+
+    assert!(item_has(src, "TyDecl", "Shape"));
+    assert!(item_has(src, "FnDecl", "area"));
+    assert!(item_has(src, "FnDecl", "name"));
+}
+
+#[test]
+fn impls() {
+    assert!(item_has("impl Point { fn new() {} }", "FnDecl", "new"));
+    assert!(item_has("impl Point { fn new() {} }", "Type", "Point"));
+
+    assert!(item_has("impl Clone for Point {}", "Type", "Clone"));
+    assert!(item_has("impl Clone for Point {}", "Type", "Point"));
+}
+
+#[test]
+fn expressions() {
+    let src = "f(1, \"a\").len()";
+
+    assert!(expr_has(src, "FnCall", "f"));
+    assert!(expr_has(src, "FnCall", "len"));
+    assert!(expr_has(src, "NumberLit", "1"));
+    assert!(expr_has(src, "StringLit", "&quot;a&quot;"));
+}
+
+#[test]
+fn statements() {
+    let src = "fn f() -> u8 { let x = 1; x }";
+
+    assert!(item_has(src, "VarDecl", "x"));
+    assert!(item_has(src, "ReturnStmt", "x"));
+}
+
+#[test]
+fn patterns() {
+    let src = "match o { Some(v) => v, None => 0 }";
+
+    assert!(expr_has(src, "VarDecl", "v"));
+    assert!(expr_has(src, "Type", "Some"));
+    assert!(expr_has(src, "Type", "None"));
+}
+
+#[test]
+fn types() {
+    assert!(ty_has("&'a mut [Foo]", "LifeTime", "'a"));
+    assert!(ty_has("&'a mut [Foo]", "Type", "Foo"));
+    assert!(ty_has("(Foo, *const Bar)", "Type", "Bar"));
+    assert!(ty_has("fn(Foo) -> Bar", "Type", "Bar"));
+}
+
+#[test]
+fn generic_args() {
+    assert!(ty_has("Box<Fn(Foo) -> Bar>", "Type", "Foo"));
+    assert!(ty_has("Box<Fn(Foo) -> Bar>", "Type", "Bar"));
+    assert!(ty_has("Iterator<Item = Foo>", "Type", "Foo"));
+}
+
+#[test]
+fn bounds() {
+    let src = "fn f<T : Show>(t : T) where T : Clone {}";
+
+    assert!(item_has(src, "GenDecl", "T"));
+    assert!(item_has(src, "GenConstraint", "Show"));
+    assert!(item_has(src, "GenConstraint", "Clone"));
+}
+
+#[test]
+fn statics() {
+    assert!(item_has("static mut COUNT : u32 = 0;", "VarDecl", "COUNT"));
+    assert!(item_has("const MAX : u32 = 10;", "VarDecl", "MAX"));
+}
+
+#[test]
+fn mods() {
+    assert!(item_has("mod inner { fn f() {} }", "FnDecl", "f"));
+    assert!(item_has("extern { fn abs(x : i32) -> i32; static errno : i32; }", "FnDecl", "abs"));
+    assert!(item_has("extern { fn abs(x : i32) -> i32; static errno : i32; }", "VarDecl", "errno"));
+}
+
+#[test]
+fn macro_params() {
+    let src = "macro_rules! m { ($v:ident) => { let $v: u8 = 0; } }";
+
+    assert!(item_has(src, "MacDecl", "m"));
+    assert!(item_has(src, "MacParam", "$"));
+    assert!(item_has(src, "MacParam", "ident"));
+    assert!(item_has(src, "MacParam", "v"));
+    assert!(!item_has(src, "MacParam", "u8"));
+}
+
+#[test]
+fn macro_calls() {
+    let src = "vec![f(x), 2]";
+
+    assert!(expr_has(src, "MacCall", "vec"));
+    assert!(expr_has(src, "NumberLit", "2"));
+}
+
+#[test]
+fn attributes() {
+    let src = "#[derive(Clone)] struct Point;";
+
+    assert!(item_has(src, "Attribute", "derive"));
+    assert!(item_has(src, "Attribute", "Clone"));
+    assert!(item_has(src, "TyDecl", "Point"));
+}
+
+#[test]
+fn comments() {
+    let src = "/// Docs\nfn f() {\n    // note\n}";
+
+    assert!(item_has(src, "DocComment", "/// Docs"));
+    assert!(item_has(src, "Comment", "// note"));
+    assert!(item_has(src, "FnDecl", "f"));
+}
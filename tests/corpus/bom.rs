@@ -0,0 +1,6 @@
+﻿//a byte order mark comes before this comment
+
+fn main() {
+    let greeting = "hello";
+    println!("{}", greeting);
+}
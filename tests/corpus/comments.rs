@@ -0,0 +1,40 @@
+//! Inner doc comments come before everything else
+//! and may run over several lines.
+
+/*! A block inner doc comment */
+
+// A plain line comment
+/* A block comment /* that nests */ and goes on */
+
+/// An outer doc comment on a struct
+#[derive(Clone)]
+pub struct Counter {
+    /// The count so far
+    count : u32, // trailing comment after a field
+    /* a comment before a field */ step : u32
+}
+
+/** A block doc comment */
+impl Counter {
+    pub fn new(/* no arguments */) -> Counter {
+        Counter { count : 0, /* inline */ step : 1 }
+    }
+
+    pub fn tick(&mut self) -> u32 {
+        self.count += self.step; // add a step
+        //a comment right before the trailing expression
+        self.count
+    }
+}
+
+fn main() {
+    let mut counter = Counter::new();
+
+    /*
+     * a block comment over several lines
+     */
+    for _ in 0..3 {
+        counter.tick();
+    }
+}
+// a comment on the last line, without a newline after it
\ No newline at end of file
@@ -0,0 +1,16 @@
+//Modules declared without a body are never loaded, wherever they point
+
+#[path = "/etc/passwd"]
+mod passwd;
+
+mod missing;
+
+pub mod inline {
+    mod nested;
+
+    pub fn inside() {}
+}
+
+fn main() {
+    inline::inside();
+}
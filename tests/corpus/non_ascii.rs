@@ -0,0 +1,17 @@
+//Literals and comments may hold any unicode: ünïcödé, 日本語, ∀x∃y
+
+static GREETING : &'static str = "grüß dich";
+static ESCAPED : &'static str = "tab\there, quote \" and \u{2603}";
+static RAW : &'static str = r#"a "raw" string with ✓ and \d"#;
+static BYTES : &'static [u8] = b"bytes <&>";
+
+fn main() {
+    let snowman = '☃';
+    let cat = "猫";
+    let byte = b'>';
+    let nbsp = "a\u{a0}b";
+
+    /* ελληνικά in a block comment */
+    println!("{} {} {} {} {}", GREETING, snowman, cat, byte, nbsp);
+    println!("{} {} {:?}", ESCAPED, RAW, BYTES);
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+//`>>` both shifts and closes two generic argument lists
+type Nested = Vec<Vec<u8>>;
+type Deeper<T> = Option<Box<Vec<T>>>;
+
+fn halve(x : u32) -> u32 {
+    x >> 1
+}
+
+fn table() -> HashMap<String, Vec<Option<u8>>> {
+    HashMap::new()
+}
+
+fn main() {
+    let mut bits : Vec<Vec<u32>> = Vec::new();
+    let mut x = 1024u32 >> 2;
+
+    x >>= 1;
+    x <<= 2;
+
+    bits.push(vec![x >> 3, x << 1, halve(x)]);
+
+    let empty : Vec<Vec<Vec<i8>>> = vec![];
+    let greater = x >= 2 && x > 1;
+
+    let _ = (empty, greater, table());
+}
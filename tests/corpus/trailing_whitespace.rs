@@ -0,0 +1,16 @@
+use std::fmt;   
+	
+//comments and lines keep their trailing whitespace  
+struct Point {
+    x : i32,  
+    y : i32	
+}
+
+impl fmt::String for Point {   
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+
+   
@@ -0,0 +1,124 @@
+#![feature(slicing_syntax)]
+
+extern crate highlightrs;
+
+use highlightrs::{highlight, ToCss, Class, ErrorKind};
+
+//Puts the name of each token's class in its class attribute
+#[derive(Clone)]
+struct ClassNames;
+
+impl ToCss for ClassNames {
+    fn to_css(self, clazz : &Class) -> String {
+        format!("{:?}", clazz)
+    }
+}
+
+//Whether html highlights text with class somewhere
+fn has(html : &str, class : &str, text : &str) -> bool {
+    html.contains(&format!("<span class=\"{}\">{}</span>", class, text)[])
+}
+
+#[test]
+fn expr() {
+    let html = highlight::expr("f(a) + 1", ClassNames).unwrap();
+    assert!(has(&html[], "FnCall", "f"));
+}
+
+#[test]
+fn stmt() {
+    let html = highlight::stmt("let x = 1;", ClassNames).unwrap();
+    assert!(has(&html[], "VarDecl", "x"));
+}
+
+#[test]
+fn item() {
+    let html = highlight::item("fn foo() {}", ClassNames).unwrap();
+    assert!(has(&html[], "FnDecl", "foo"));
+}
+
+#[test]
+fn ty() {
+    let html = highlight::ty("Option<Foo>", ClassNames).unwrap();
+    assert!(has(&html[], "Type", "Foo"));
+}
+
+#[test]
+fn pat() {
+    let html = highlight::pat("Some(x)", ClassNames).unwrap();
+    assert!(has(&html[], "VarDecl", "x"));
+}
+
+#[test]
+fn auto_takes_the_first_grammar_that_parses() {
+    let program = "fn foo() {}";
+    assert_eq!(highlight::auto(program, ClassNames).unwrap(), highlight::program(program, ClassNames).unwrap());
+
+    let expr = "a + 1";
+    assert_eq!(highlight::auto(expr, ClassNames).unwrap(), highlight::expr(expr, ClassNames).unwrap());
+
+    let stmt = "let x = 1;";
+    assert_eq!(highlight::auto(stmt, ClassNames).unwrap(), highlight::stmt(stmt, ClassNames).unwrap());
+
+    let ty = "Vec<u8>";
+    assert_eq!(highlight::auto(ty, ClassNames).unwrap(), highlight::ty(ty, ClassNames).unwrap());
+}
+
+#[test]
+fn syntax_error() {
+    let error = highlight::expr("1 +", ClassNames).unwrap_err();
+
+    assert_eq!(error.kind, ErrorKind::Syntax);
+    assert!(error.span.is_some());
+}
+
+#[test]
+fn wrong_fragment() {
+    let error = highlight::expr("a; b", ClassNames).unwrap_err();
+
+    assert_eq!(error.kind, ErrorKind::WrongFragment);
+    assert!(error.span.is_none());
+}
+
+#[test]
+fn tokens() {
+    let html = highlight::tokens("fn main() { ... }", ClassNames).unwrap();
+
+    assert!(has(&html[], "Keyword", "fn"));
+    assert!(has(&html[], "Operator", "..."));
+}
+
+#[test]
+fn auto_falls_back_to_tokens() {
+    let snippet = "... }";
+    assert_eq!(highlight::auto(snippet, ClassNames).unwrap(), highlight::tokens(snippet, ClassNames).unwrap());
+}
+
+#[test]
+fn tolerant_keeps_the_statements_before_the_error() {
+    let src  = "fn main() { let a = 1; let b = 2; let c = ; }";
+    let html = highlight::tolerant(src, ClassNames).unwrap();
+
+    assert!(has(&html[], "VarDecl", "b"));
+    assert!(!has(&html[], "VarDecl", "c"));
+}
+
+#[test]
+fn tolerant_recovers_from_an_error_inside_a_token() {
+    let src  = "fn main() { let s = \"a\\qb\"; }";
+    let html = highlight::tolerant(src, ClassNames).unwrap();
+
+    assert!(has(&html[], "FnDecl", "main"));
+    assert!(html.contains("class=\"Error\""));
+}
+
+#[test]
+fn tolerant_marks_the_error() {
+    let src = "fn f() {} fn g() { let x = ; }";
+
+    let message = highlight::program(src, ClassNames).unwrap_err().message;
+
+    let html = highlight::tolerant(src, ClassNames).unwrap();
+    assert!(has(&html[], "FnDecl", "g"));
+    assert!(html.contains(&format!("<span class=\"Error\" title=\"{}\">;</span>", message)[]));
+}
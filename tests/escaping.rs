@@ -0,0 +1,57 @@
+#![feature(slicing_syntax)]
+
+extern crate highlightrs;
+
+use highlightrs::{highlight, ToCss, Class};
+
+//Puts the name of each token's class in its class attribute
+#[derive(Clone)]
+struct ClassNames;
+
+impl ToCss for ClassNames {
+    fn to_css(self, clazz : &Class) -> String {
+        format!("{:?}", clazz)
+    }
+}
+
+//Gives every class css that would end the attribute early if left unescaped
+#[derive(Clone)]
+struct Quoting;
+
+impl ToCss for Quoting {
+    fn to_css(self, _ : &Class) -> String {
+        "a\"b<c".to_string()
+    }
+}
+
+#[test]
+fn string_literal() {
+    let html = highlight::expr("\"<script>\"", ClassNames).unwrap();
+
+    assert_eq!(&html[], "<pre style=\"background:#efffef\"><code>\
+                         <span class=\"StringLit\">&quot;&lt;script&gt;&quot;</span>\
+                         </code></pre>");
+}
+
+#[test]
+fn css_value() {
+    let html = highlight::expr("1", Quoting).unwrap();
+
+    assert_eq!(&html[], "<pre style=\"background:#efffef\"><code>\
+                         <span class=\"a&quot;b&lt;c\">1</span>\
+                         </code></pre>");
+}
+
+#[test]
+fn error_title() {
+    let src = "fn f() {} fn g() { let x = 1 \"a\"; }";
+
+    let message = highlight::program(src, ClassNames).unwrap_err().message;
+    assert!(message.contains("\"a\""));
+
+    let title = message.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
+                       .replace("\"", "&quot;").replace("'", "&#39;");
+
+    let html = highlight::tolerant(src, ClassNames).unwrap();
+    assert!(html.contains(&format!("<span class=\"Error\" title=\"{}\">&quot;a&quot;</span>", title)[]));
+}
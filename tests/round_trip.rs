@@ -0,0 +1,60 @@
+extern crate highlightrs;
+
+use highlightrs::{highlight, DefaultInline};
+
+//Highlights src as a program, which checks the markup strips back to it byte for byte
+fn round_trips(name : &str, src : &str) {
+    if let Err(e) = highlight::verified_program(src, DefaultInline) {
+        panic!("{} doesn't round trip: {}", name, e);
+    }
+}
+
+#[test]
+fn main_rs() {
+    round_trips("main.rs", include_str!("../src/main.rs"));
+}
+
+#[test]
+fn lib_rs() {
+    round_trips("lib.rs", include_str!("../src/lib.rs"));
+}
+
+#[test]
+fn html_highlighter_rs() {
+    round_trips("html_highlighter.rs", include_str!("../src/html_highlighter.rs"));
+}
+
+#[test]
+fn byte_order_mark() {
+    round_trips("bom.rs", include_str!("corpus/bom.rs"));
+}
+
+#[test]
+fn trailing_whitespace() {
+    round_trips("trailing_whitespace.rs", include_str!("corpus/trailing_whitespace.rs"));
+}
+
+#[test]
+fn comments() {
+    round_trips("comments.rs", include_str!("corpus/comments.rs"));
+}
+
+#[test]
+fn shifts() {
+    round_trips("shifts.rs", include_str!("corpus/shifts.rs"));
+}
+
+#[test]
+fn non_ascii_literals() {
+    round_trips("non_ascii.rs", include_str!("corpus/non_ascii.rs"));
+}
+
+#[test]
+fn external_mods() {
+    round_trips("external_mods.rs", include_str!("corpus/external_mods.rs"));
+}
+
+#[test]
+fn third_party_macros() {
+    round_trips("matches.rs", include_str!("corpus/matches.rs"));
+}